@@ -1,44 +1,60 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Display,
     fs::File,
-    io::{self, BufRead, BufReader},
-    process::{Command, Stdio},
+    io::{self, BufRead, BufReader, Read},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::PathBuf,
+    process::{Command, Output, Stdio},
     sync::{
         atomic::{AtomicBool, Ordering},
         mpsc::{self, Receiver},
+        Arc,
     },
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use color_eyre::{
-    eyre::{bail, WrapErr},
-    Result,
-};
+use clap::ValueEnum;
+use color_eyre::{eyre::WrapErr, Result};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use ratatui::Frame;
-
+use ratatui::{style::Color, Frame};
+use regex::Regex;
+
+use crate::children;
+use crate::export::{self, ExportFormat, ExportRange, Series};
+use crate::expr::Derived;
+use crate::format::{LineParser, Metric};
+use crate::health::{Health, Reporter, SourceState};
+use crate::panel::{Layout, Panel};
+use crate::record::Recorder;
+use crate::replay::ReplayClock;
+use crate::source::{CommandDefaults, ExecMode, RestartPolicy, SourceSpec};
 use crate::term;
+use crate::transform::{PipelineState, Transform, Transforms};
 use crate::ui;
 
+/// How long a status message stays in the legend
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ScreenMode {
     Main,
     Pause,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, ValueEnum)]
 pub enum ChartScale {
-    Liner,
+    #[value(alias = "liner")]
+    Linear,
     Asinh,
 }
 
 impl ChartScale {
     pub fn next(&self) -> Self {
         match self {
-            ChartScale::Liner => ChartScale::Asinh,
-            ChartScale::Asinh => ChartScale::Liner,
+            ChartScale::Linear => ChartScale::Asinh,
+            ChartScale::Asinh => ChartScale::Linear,
         }
     }
 }
@@ -46,7 +62,7 @@ impl ChartScale {
 impl Display for ChartScale {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ChartScale::Liner => f.write_str("liner"),
+            ChartScale::Linear => f.write_str("linear"),
             ChartScale::Asinh => f.write_str("asinh"),
         }
     }
@@ -54,16 +70,111 @@ impl Display for ChartScale {
 
 #[derive(Default)]
 pub struct Signals {
+    /// Values as they were received
     pub original: Vec<f64>,
+    /// Values after the series transforms, the same as `original` without them
+    pub values: Vec<f64>,
     pub chart: Vec<(f64, f64)>,
+    /// Values without the smoothing transforms, kept only while they are shown behind the smoothed line
+    pub raw: Vec<f64>,
+    pub raw_chart: Vec<(f64, f64)>,
+    /// The source of the first point, none for the derived series
+    pub source: Option<usize>,
+    /// First point changed since the transforms were last applied
+    dirty: usize,
+    transformed: PipelineState,
+    unsmoothed: PipelineState,
 }
 
 impl Signals {
+    /// Inserts the point keeping the points ordered by time
+    fn insert(&mut self, x_time: f64, original: f64, scaled: f64) {
+        let idx = match self.chart.last() {
+            Some(last) if last.0 > x_time => self.chart.partition_point(|x| x.0 <= x_time),
+            _ => self.chart.len(),
+        };
+        self.original.insert(idx, original);
+        self.values.insert(idx, original);
+        self.chart.insert(idx, (x_time, scaled));
+        self.dirty = self.dirty.min(idx);
+    }
+
+    /// Replaces the point at exactly the same time or inserts a new one
+    fn upsert(&mut self, x_time: f64, original: f64, scaled: f64) {
+        let idx = self.chart.partition_point(|x| x.0 < x_time);
+        match self.chart.get_mut(idx) {
+            Some(point) if point.0 == x_time => {
+                point.1 = scaled;
+                self.original[idx] = original;
+                self.values[idx] = original;
+                self.dirty = self.dirty.min(idx);
+            }
+            _ => self.insert(x_time, original, scaled),
+        }
+    }
+
+    /// Recomputes the transformed values and their chart points from the
+    /// first changed point on
+    fn refresh(&mut self, pipeline: &[Transform], scale_mode: ChartScale, with_raw: bool) {
+        let from = self.dirty.min(self.original.len());
+        let times: Vec<f64> = self.chart.iter().map(|(time, _)| *time).collect();
+        let values = self
+            .transformed
+            .update(pipeline, &times, &self.original, from);
+        self.values.truncate(from);
+        self.values.extend_from_slice(&values[from..]);
+        for (point, value) in self.chart[from..].iter_mut().zip(&self.values[from..]) {
+            point.1 = App::scale(scale_mode, *value);
+        }
+
+        if with_raw && pipeline.iter().any(Transform::is_smoothing) {
+            let unsmoothed: Vec<Transform> = pipeline
+                .iter()
+                .filter(|t| !t.is_smoothing())
+                .copied()
+                .collect();
+            let raw = self
+                .unsmoothed
+                .update(&unsmoothed, &times, &self.original, from);
+            self.raw.truncate(from);
+            self.raw.extend_from_slice(&raw[from..]);
+            self.raw_chart.truncate(from);
+            self.raw_chart.extend(
+                times[from..]
+                    .iter()
+                    .zip(&self.raw[from..])
+                    .map(|(time, value)| (*time, App::scale(scale_mode, *value))),
+            );
+        } else {
+            self.raw.clear();
+            self.raw_chart.clear();
+            self.unsmoothed.clear();
+        }
+        self.dirty = self.original.len();
+    }
+
+    /// Makes the next refresh run all the points through the transforms,
+    /// e.g. after the transforms were toggled
+    fn reset_transforms(&mut self) {
+        self.dirty = 0;
+        self.transformed.clear();
+        self.unsmoothed.clear();
+    }
+
     fn drain(&mut self, oldest: f64) -> usize {
         let drain_to = self.chart.partition_point(|x| x.0 < oldest);
         if drain_to > 0 {
             self.chart.drain(..drain_to);
             self.original.drain(..drain_to);
+            self.values.drain(..drain_to);
+            self.dirty = self.dirty.saturating_sub(drain_to);
+            self.transformed.drain(drain_to);
+            self.unsmoothed.drain(drain_to);
+        }
+        let raw_drain_to = self.raw_chart.partition_point(|x| x.0 < oldest);
+        if raw_drain_to > 0 {
+            self.raw_chart.drain(..raw_drain_to);
+            self.raw.drain(..raw_drain_to);
         }
         self.original.len()
     }
@@ -73,6 +184,8 @@ pub struct Signal {
     pub name: String,
     pub x_time: f64,
     pub value: f64,
+    /// Index of the source that produced the signal, see `Reporter::source`
+    pub source: usize,
 }
 
 #[derive(Default)]
@@ -85,13 +198,20 @@ pub struct ChartBounds {
     pub max_values: HashMap<String, f64>,
     pub label_values: HashMap<String, f64>,
     pub cursor_points: [(f64, f64); 3],
+    /// Vertical lines at the restarts of the panel's processes
+    pub restart_marks: Vec<[(f64, f64); 2]>,
 }
 
 #[derive(Debug)]
 pub struct ChartLine<'a> {
     pub color_idx: usize,
     pub name: String,
-    pub data: &'a [(f64, f64)],
+    /// Continuous parts of the line, gaps (NaN points) are left out
+    pub segments: Vec<&'a [(f64, f64)]>,
+    /// The raw line drawn behind the smoothed one
+    pub dimmed: bool,
+    /// A mark of a process restart
+    pub restart: bool,
 }
 
 pub struct App {
@@ -102,17 +222,50 @@ pub struct App {
     pub axis_labels: bool,
     pub legend: bool,
     pub show_cursor: bool,
+    pub tick_rate: Duration,
+    /// Colors of the series, the default palette if empty
+    pub colors: Vec<Color>,
+    pub export_format: ExportFormat,
+    pub transforms: Transforms,
+    /// Show the raw line behind the smoothed one
+    pub show_raw: bool,
+    pub exprs: Vec<Derived>,
+    /// Width in seconds of the time buckets derived series are aligned by, the nearest points are used without it
+    pub expr_bucket: Option<f64>,
+    pub panels: Vec<Panel>,
+    pub layout: Layout,
+    /// The legend entries are ordered by their source and labeled with it
+    pub group_legend: bool,
 
     input: Receiver<Signal>,
     current_mode: ScreenMode,
     start_point: Instant,
     elapsed: f64,
     signals: BTreeMap<String, Signals>,
-    tick_rate: Duration,
     show_help: bool,
 
-    chart_bounds: ChartBounds,
+    /// Bounds of every chart panel
+    chart_bounds: Vec<ChartBounds>,
     cursor_position: f64,
+    replay: Option<Arc<ReplayClock>>,
+    recorder: Option<Recorder>,
+    status: Option<(String, Instant)>,
+    transforms_enabled: bool,
+    /// Names of the series whose transforms are toggled off in the series list
+    untransformed: BTreeSet<String>,
+    /// Names of the series hidden in the series list
+    hidden: BTreeSet<String>,
+    show_series: bool,
+    selected_series: usize,
+    /// Only the series matching the search are shown
+    search: Option<Regex>,
+    /// The search being typed after `/`
+    search_input: Option<String>,
+    health: Health,
+    /// Names of the sources by their index, taken from the health on every frame
+    source_names: Vec<String>,
+    show_health: bool,
+    selected_source: usize,
 
     exit: AtomicBool,
 }
@@ -121,33 +274,80 @@ impl App {
     pub fn new(input: Receiver<Signal>, start_time: Instant) -> Self {
         let window = Duration::from_secs(60);
         Self {
-            // TODO: confugure this
             history: Duration::from_secs(3600),
             window,
             move_speed: 1.0,
-            scale_mode: ChartScale::Liner,
+            scale_mode: ChartScale::Linear,
             axis_labels: false,
             legend: true,
+            tick_rate: Duration::from_millis(250),
+            colors: Vec::new(),
+            export_format: ExportFormat::Csv,
+            transforms: Transforms::default(),
+            show_raw: false,
+            exprs: Vec::new(),
+            expr_bucket: None,
+            panels: Vec::new(),
+            layout: Layout::Stacked,
+            group_legend: false,
 
             input,
             current_mode: ScreenMode::Main,
             elapsed: 0.0,
             start_point: start_time,
             signals: BTreeMap::new(),
-            tick_rate: Duration::from_millis(250),
             show_help: false,
 
-            chart_bounds: Default::default(),
+            chart_bounds: Vec::new(),
             show_cursor: false,
             cursor_position: window.as_secs_f64() / 2.0,
+            replay: None,
+            recorder: None,
+            status: None,
+            transforms_enabled: true,
+            untransformed: BTreeSet::new(),
+            hidden: BTreeSet::new(),
+            show_series: false,
+            selected_series: 0,
+            search: None,
+            search_input: None,
+            health: Health::default(),
+            source_names: Vec::new(),
+            show_health: false,
+            selected_source: 0,
 
             exit: AtomicBool::new(false),
         }
     }
+
+    /// Sets the chart window with the cursor in its middle
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+        self.cursor_position = window.as_secs_f64() / 2.0;
+    }
+
+    /// Follows the replay clock instead of the wall clock
+    pub fn with_replay(mut self, clock: Arc<ReplayClock>) -> Self {
+        self.replay = Some(clock);
+        self
+    }
+
+    /// Shows the state of the sources in the health panel
+    pub fn with_health(mut self, health: Health) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Writes every received signal to the recording
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     pub fn run(&mut self, terminal: &mut term::Tui) -> Result<()> {
         let mut last_tick = Instant::now();
 
-        while !self.exit.load(Ordering::Relaxed) {
+        while !self.exit.load(Ordering::Relaxed) && children::interrupted().is_none() {
             self.set_chart_bounds();
             terminal.draw(|frame| self.render_frame(frame))?;
 
@@ -169,6 +369,14 @@ impl App {
         if self.show_help {
             ui::render_help(frame);
         }
+        if self.show_series {
+            ui::render_series(frame, self);
+        }
+        if self.show_health {
+            let sources = self.health.sources();
+            let selected = self.selected_source.min(sources.len().saturating_sub(1));
+            ui::render_health(frame, &sources, selected);
+        }
     }
 
     /// updates the application's state based on user input
@@ -185,6 +393,18 @@ impl App {
     }
 
     fn handle_key_event(&mut self, key: KeyEvent) -> Result<()> {
+        if self.show_series {
+            self.handle_series_key_event(key);
+            return Ok(());
+        }
+        if self.search_input.is_some() {
+            self.handle_search_key_event(key);
+            return Ok(());
+        }
+        if self.show_health {
+            self.handle_health_key_event(key);
+            return Ok(());
+        }
         match key.code {
             KeyCode::Char('q') => {
                 if self.show_help {
@@ -195,6 +415,19 @@ impl App {
             }
             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.exit(),
             KeyCode::Char('?') => self.show_help = !self.show_help,
+            KeyCode::Char('g') => self.group_legend = !self.group_legend,
+            KeyCode::Char('S') => {
+                self.show_health = !self.show_health;
+                self.show_help = false;
+            }
+            KeyCode::Char('v') => {
+                self.show_series = true;
+                self.show_help = false;
+            }
+            KeyCode::Char('/') => {
+                let current = self.search.as_ref().map(|re| re.as_str().to_string());
+                self.search_input = Some(current.unwrap_or_default());
+            }
             KeyCode::Char('w') => {
                 self.window = Duration::from_secs_f64(self.window.as_secs_f64() * 0.8);
                 self.cursor_position *= 0.8;
@@ -204,7 +437,7 @@ impl App {
                 self.cursor_position *= 1.2;
             }
             KeyCode::Char('h') => {
-                let x_sec = self.start_point.elapsed().as_secs_f64();
+                let x_sec = self.now();
                 let oldest = x_sec - self.history.as_secs_f64();
                 let keys: Vec<String> = self.signals.keys().cloned().collect();
                 for k in keys {
@@ -230,11 +463,45 @@ impl App {
                     ScreenMode::Main => ScreenMode::Pause,
                     ScreenMode::Pause => ScreenMode::Main,
                 };
+                if let Some(clock) = &self.replay {
+                    clock.set_paused(self.in_pause());
+                }
+            }
+            KeyCode::Char('[') | KeyCode::Char(']') if self.replay.is_some() => {
+                let offset = if key.code == KeyCode::Char('[') {
+                    -self.window()
+                } else {
+                    self.window()
+                };
+                if let Some(clock) = &self.replay {
+                    clock.seek(offset);
+                    self.elapsed = clock.now();
+                }
+            }
+            KeyCode::Char('+') | KeyCode::Char('-') if self.replay.is_some() => {
+                let factor = if key.code == KeyCode::Char('+') {
+                    2.0
+                } else {
+                    0.5
+                };
+                if let Some(clock) = &self.replay {
+                    clock.set_speed(clock.speed() * factor);
+                }
             }
             KeyCode::Char('s') => {
                 self.scale_mode = self.scale_mode.next();
                 self.apply_new_scale_mode()
             }
+            KeyCode::Char('r') if !self.transforms.is_empty() => {
+                self.transforms_enabled = !self.transforms_enabled;
+                self.apply_transforms();
+                let state = if self.transforms_enabled { "on" } else { "off" };
+                self.status = Some((format!("transforms {state}"), Instant::now()));
+            }
+            KeyCode::Char('R') if !self.transforms.is_empty() => {
+                self.show_raw = !self.show_raw;
+                self.apply_transforms();
+            }
             KeyCode::Char('m') => self.move_speed /= 10.0,
             KeyCode::Char('M') => self.move_speed *= 10.0,
             KeyCode::Left if self.in_pause() && key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -252,38 +519,213 @@ impl App {
                 self.cursor_position = new_pos.clamp(0.0, self.window());
             }
             KeyCode::Char('c') => self.show_cursor = !self.show_cursor,
+            KeyCode::Char('e') | KeyCode::Char('E') => {
+                let range = if key.code == KeyCode::Char('e') {
+                    ExportRange::Window
+                } else {
+                    ExportRange::All
+                };
+                let path = format!(
+                    "tlook-{:.0}.{}",
+                    unix_time(self.start_point) + self.start_point.elapsed().as_secs_f64(),
+                    self.export_format.extension()
+                );
+                let status = match self.export(&path, self.export_format, range) {
+                    Ok(()) => format!("exported to {path}"),
+                    Err(e) => {
+                        log::error!("export to {path} failed: {e}");
+                        format!("export failed: {e}")
+                    }
+                };
+                self.status = Some((status, Instant::now()));
+            }
             _ => {}
         }
         Ok(())
     }
 
+    /// Keys of the series list, it stays on top until closed
+    fn handle_series_key_event(&mut self, key: KeyEvent) {
+        let names: Vec<String> = self.signals.keys().cloned().collect();
+        self.selected_series = self.selected_series.min(names.len().saturating_sub(1));
+        let selected = names.get(self.selected_series);
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.exit(),
+            KeyCode::Char('v') | KeyCode::Char('q') | KeyCode::Esc => self.show_series = false,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_series = self.selected_series.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_series =
+                    (self.selected_series + 1).min(names.len().saturating_sub(1));
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                if let Some(name) = selected {
+                    if !self.hidden.remove(name) {
+                        self.hidden.insert(name.clone());
+                    }
+                }
+            }
+            KeyCode::Char('o') => {
+                if let Some(name) = selected {
+                    self.hidden = names.iter().filter(|n| *n != name).cloned().collect();
+                }
+            }
+            KeyCode::Char('i') => {
+                self.hidden = names
+                    .into_iter()
+                    .filter(|name| !self.hidden.contains(name))
+                    .collect();
+            }
+            KeyCode::Char('a') => self.hidden.clear(),
+            KeyCode::Char('r') => {
+                let Some(name) = selected.filter(|name| !self.transforms.pipeline(name).is_empty())
+                else {
+                    return;
+                };
+                if !self.untransformed.remove(name) {
+                    self.untransformed.insert(name.clone());
+                }
+                self.apply_transforms();
+            }
+            _ => {}
+        }
+    }
+
+    /// Keys of the health panel, it stays on top until closed
+    fn handle_health_key_event(&mut self, key: KeyEvent) {
+        let count = self.health.sources().len();
+        self.selected_source = self.selected_source.min(count.saturating_sub(1));
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.exit(),
+            KeyCode::Char('S') | KeyCode::Char('q') | KeyCode::Esc => self.show_health = false,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_source = self.selected_source.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_source = (self.selected_source + 1).min(count.saturating_sub(1));
+            }
+            _ => {}
+        }
+    }
+
+    /// Keys of the search input, Enter applies the search and an empty one shows all the series
+    fn handle_search_key_event(&mut self, key: KeyEvent) {
+        let Some(input) = &mut self.search_input else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.exit(),
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.search_input = None,
+            KeyCode::Enter => {
+                let input = self.search_input.take().unwrap_or_default();
+                if input.is_empty() {
+                    self.search = None;
+                    return;
+                }
+                match Regex::new(&input) {
+                    Ok(re) => self.search = Some(re),
+                    Err(e) => {
+                        log::warn!("invalid search {input:?}: {e}");
+                        self.status = Some((format!("invalid regex {input:?}"), Instant::now()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The search as shown in the legend, with a cursor while it is typed
+    pub fn search(&self) -> Option<String> {
+        match (&self.search_input, &self.search) {
+            (Some(input), _) => Some(format!("/{input}_")),
+            (None, Some(re)) => Some(format!("/{}", re.as_str())),
+            (None, None) => None,
+        }
+    }
+
+    /// Series for the series list as `(color index, name, source, transforms, visible)`,
+    /// the transforms toggled off are marked as such
+    pub fn series_list(&self) -> Vec<(usize, &str, &str, String, bool)> {
+        self.signals
+            .iter()
+            .enumerate()
+            .map(|(color_idx, (name, set))| {
+                let (_, source_name) = self.source_label(set.source);
+                let pipeline = self.transforms.pipeline(name);
+                let mut transforms: Vec<String> = pipeline.iter().map(|t| t.to_string()).collect();
+                if !pipeline.is_empty() && self.pipeline(name).is_empty() {
+                    transforms.push("off".to_string());
+                }
+                (
+                    color_idx,
+                    name.as_str(),
+                    source_name,
+                    transforms.join(", "),
+                    !self.hidden.contains(name),
+                )
+            })
+            .collect()
+    }
+
+    pub fn selected_series(&self) -> usize {
+        self.selected_series
+    }
+
     fn on_tick(&mut self) {
-        if self.current_mode == ScreenMode::Pause {
+        // A paused replay stops its reader, so the points can still be taken in
+        if self.in_pause() && self.replay.is_none() {
             return;
         }
-        self.elapsed = self.start_point.elapsed().as_secs_f64();
+        if !self.in_pause() {
+            self.elapsed = self.now();
+        }
 
+        let oldest = self.elapsed - self.history.as_secs_f64();
+        // Times of the new points by series
+        let mut touched: BTreeMap<String, Vec<f64>> = BTreeMap::new();
         let mut count = 0;
         for signal in self.input.try_iter() {
-            let data = self.signals.entry(signal.name.clone()).or_default();
-            data.original.push(signal.value);
-            data.chart
-                .push((signal.x_time, Self::scale(self.scale_mode, signal.value)));
-
-            let oldest = signal.x_time - self.history.as_secs_f64();
+            if let Some(recorder) = &mut self.recorder {
+                if let Err(e) = recorder.write(&signal) {
+                    log::error!("stop recording, write failed: {e}");
+                    self.recorder = None;
+                }
+            }
+            // Points carrying their own timestamp may be older than the kept history
+            if signal.x_time < oldest {
+                continue;
+            }
+            let data = self
+                .signals
+                .entry(signal.name.clone())
+                .or_insert_with(|| Signals {
+                    source: Some(signal.source),
+                    ..Default::default()
+                });
+            let scaled = Self::scale(self.scale_mode, signal.value);
+            data.insert(signal.x_time, signal.value, scaled);
             data.drain(oldest);
+            touched.entry(signal.name).or_default().push(signal.x_time);
             count += 1;
         }
+        for name in touched.keys() {
+            self.refresh_transforms(name);
+        }
+        self.derive(&mut touched, oldest);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.flush() {
+                log::error!("stop recording, flush failed: {e}");
+                self.recorder = None;
+            }
+        }
         log::debug!("tick: receive {count} signals");
     }
 
-    fn parse_input(line: &str) -> Result<(String, f64)> {
-        let Some((name, rest)) = line.split_once('=') else {
-            bail!("missing delimiter '='");
-        };
-        Ok((name.to_string(), rest.parse::<f64>()?))
-    }
-
     fn exit(&self) {
         self.exit.store(true, Ordering::Relaxed);
     }
@@ -291,18 +733,192 @@ impl App {
     fn apply_new_scale_mode(&mut self) {
         for (_, item) in self.signals.iter_mut() {
             item.chart.iter_mut().enumerate().for_each(|(idx, data)| {
-                data.1 = Self::scale(self.scale_mode, item.original[idx]);
+                data.1 = Self::scale(self.scale_mode, item.values[idx]);
             });
+            item.raw_chart
+                .iter_mut()
+                .enumerate()
+                .for_each(|(idx, data)| {
+                    data.1 = Self::scale(self.scale_mode, item.raw[idx]);
+                });
+        }
+    }
+
+    /// Transforms of the series, none while they are toggled off for all
+    /// the series or for this one
+    fn pipeline(&self, name: &str) -> Vec<Transform> {
+        if !self.transforms_enabled || self.untransformed.contains(name) {
+            Vec::new()
+        } else {
+            self.transforms.pipeline(name)
+        }
+    }
+
+    fn refresh_transforms(&mut self, name: &str) {
+        if self.transforms.is_empty() {
+            return;
+        }
+        let pipeline = self.pipeline(name);
+        if let Some(data) = self.signals.get_mut(name).filter(|_| !pipeline.is_empty()) {
+            data.refresh(&pipeline, self.scale_mode, self.show_raw);
+        }
+    }
+
+    /// Evaluates the derived series at the times of the new points of the
+    /// series they depend on, a derived series can use the previous ones
+    fn derive(&mut self, touched: &mut BTreeMap<String, Vec<f64>>, oldest: f64) {
+        let exprs = std::mem::take(&mut self.exprs);
+        for derived in &exprs {
+            let mut times: Vec<f64> = derived
+                .vars()
+                .into_iter()
+                .filter_map(|var| touched.get(var))
+                .flatten()
+                .map(|&time| match self.expr_bucket {
+                    Some(bucket) => (time / bucket).floor() * bucket,
+                    None => time,
+                })
+                .filter(|&time| time >= oldest)
+                .collect();
+            if times.is_empty() {
+                continue;
+            }
+            times.sort_by(f64::total_cmp);
+            times.dedup();
+
+            for time in times {
+                let Some(value) = derived.eval(|var| self.value_at(var, time)) else {
+                    continue;
+                };
+                let scaled = Self::scale(self.scale_mode, value);
+                let data = self.signals.entry(derived.name.clone()).or_default();
+                data.upsert(time, value, scaled);
+                touched.entry(derived.name.clone()).or_default().push(time);
+            }
+            if let Some(data) = self.signals.get_mut(&derived.name) {
+                data.drain(oldest);
+            }
+            self.refresh_transforms(&derived.name);
+        }
+        self.exprs = exprs;
+    }
+
+    /// Value of the series for a derived series at the time, the nearest point
+    /// or the last point of the time bucket starting at `time`
+    fn value_at(&self, name: &str, time: f64) -> Option<f64> {
+        let set = self.signals.get(name)?;
+        let idx = match self.expr_bucket {
+            Some(bucket) => {
+                let end = set.chart.partition_point(|(t, _)| *t < time + bucket);
+                end.checked_sub(1).filter(|&idx| set.chart[idx].0 >= time)?
+            }
+            None => {
+                let next = set.chart.partition_point(|(t, _)| *t < time);
+                match (next.checked_sub(1), set.chart.get(next)) {
+                    (Some(prev), Some((next_time, _)))
+                        if time - set.chart[prev].0 > next_time - time =>
+                    {
+                        next
+                    }
+                    (Some(prev), _) => prev,
+                    (None, Some(_)) => next,
+                    (None, None) => return None,
+                }
+            }
+        };
+        set.values.get(idx).copied()
+    }
+
+    /// Runs all the points through the transforms again and recomputes the
+    /// derived series, they are evaluated on the transformed values
+    fn apply_transforms(&mut self) {
+        let derived: BTreeSet<&String> = self.exprs.iter().map(|derived| &derived.name).collect();
+        let names: Vec<String> = self
+            .signals
+            .keys()
+            .filter(|name| !derived.contains(name))
+            .cloned()
+            .collect();
+        for name in names {
+            let pipeline = self.pipeline(&name);
+            if let Some(data) = self.signals.get_mut(&name) {
+                data.reset_transforms();
+                data.refresh(&pipeline, self.scale_mode, self.show_raw);
+            }
         }
+
+        for derived in &self.exprs {
+            self.signals.remove(&derived.name);
+        }
+        let mut touched: BTreeMap<String, Vec<f64>> = self
+            .signals
+            .iter()
+            .map(|(name, set)| {
+                (
+                    name.clone(),
+                    set.chart.iter().map(|(time, _)| *time).collect(),
+                )
+            })
+            .collect();
+        let oldest = self.elapsed - self.history.as_secs_f64();
+        self.derive(&mut touched, oldest);
     }
 
     fn scale(mode: ChartScale, value: f64) -> f64 {
+        // Non-finite values can't be drawn and are shown as gaps
+        if !value.is_finite() {
+            return f64::NAN;
+        }
         match mode {
-            ChartScale::Liner => value,
+            ChartScale::Linear => value,
             ChartScale::Asinh => value.asinh(),
         }
     }
 
+    /// Exports the original values of the series to the file
+    pub fn export(&self, path: &str, format: ExportFormat, range: ExportRange) -> io::Result<()> {
+        let origin = self
+            .replay
+            .as_ref()
+            .and_then(|clock| clock.origin())
+            .unwrap_or_else(|| unix_time(self.start_point));
+        let series: Vec<Series> = self
+            .signals
+            .iter()
+            .map(|(name, set)| {
+                let points = set
+                    .chart
+                    .iter()
+                    .zip(set.original.iter())
+                    .filter(|((time, _), _)| range == ExportRange::All || self.on_screen(*time))
+                    .map(|((time, _), value)| (origin + time, *value))
+                    .collect();
+                (name.as_str(), points)
+            })
+            .collect();
+        export::export(path, format, &series)
+    }
+
+    /// A message shown in the legend for a few seconds after an action
+    pub fn status(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .filter(|(_, since)| since.elapsed() < STATUS_TIMEOUT)
+            .map(|(status, _)| status.as_str())
+    }
+
+    /// Current time on the chart, it follows the replay clock in the replay mode
+    fn now(&self) -> f64 {
+        match &self.replay {
+            Some(clock) => clock.now(),
+            None => self.start_point.elapsed().as_secs_f64(),
+        }
+    }
+
+    pub fn replay_speed(&self) -> Option<f64> {
+        self.replay.as_ref().map(|clock| clock.speed())
+    }
+
     pub fn elapsed(&self) -> f64 {
         self.elapsed
     }
@@ -322,22 +938,71 @@ impl App {
         self.current_mode == ScreenMode::Pause
     }
 
-    pub fn chart_bounds(&self) -> &ChartBounds {
-        &self.chart_bounds
+    pub fn chart_bounds(&self, panel: usize) -> &ChartBounds {
+        &self.chart_bounds[panel]
+    }
+
+    /// Number of the chart panels, the series matching none of the `--panel`s get one more
+    pub fn panel_count(&self) -> usize {
+        self.chart_bounds.len()
+    }
+
+    /// Panel title, the series matching none of the `--panel`s are shown in the last one
+    pub fn panel_title(&self, panel: usize) -> &str {
+        self.panels
+            .get(panel)
+            .map_or("*", |panel| panel.title.as_str())
     }
+
+    fn panel_of(&self, name: &str, set: &Signals) -> usize {
+        let source = set.source.map(|idx| idx + 1);
+        self.panels
+            .iter()
+            .position(|panel| panel.matches(name, source))
+            .unwrap_or(self.panels.len())
+    }
+
+    /// Visible series of the panel with their color index
+    fn panel_signals(&self, panel: usize) -> impl Iterator<Item = (usize, (&String, &Signals))> {
+        self.signals
+            .iter()
+            .enumerate()
+            .filter(move |(_, (name, set))| {
+                !self.hidden.contains(*name)
+                    && self.search.as_ref().is_none_or(|re| re.is_match(name))
+                    && self.panel_of(name, set) == panel
+            })
+    }
+
     fn set_chart_bounds(&mut self) {
+        let assigned = self.panels.len();
+        let unassigned = self.panel_signals(assigned).next().is_some();
+        let count = if unassigned || assigned == 0 {
+            assigned + 1
+        } else {
+            assigned
+        };
+        self.source_names = self.health.names();
+        let restarts = self.health.restarts();
+        self.chart_bounds = (0..count)
+            .map(|panel| self.panel_bounds(panel, &restarts))
+            .collect();
+    }
+
+    fn panel_bounds(&self, panel: usize, restarts: &[(usize, Vec<Instant>)]) -> ChartBounds {
         let mut max_values = HashMap::new();
         let mut cursor_values = HashMap::new();
         let cursor_point = self.cursor_point();
-        let (max_name_len, original_min_max, scaled_min_max) = self
-            .signals
-            .iter()
-            .map(|(name, set)| {
+        let (max_name_len, mut original_min_max, mut scaled_min_max) = self
+            .panel_signals(panel)
+            .map(|(_, (name, set))| {
                 let (original_min_max, scaled_min_max) = set
-                    .original
+                    .values
                     .iter()
                     .zip(set.chart.iter())
-                    .filter(|(_, (elapsed, _))| self.on_screen(*elapsed))
+                    .filter(|(original, (elapsed, _))| {
+                        original.is_finite() && self.on_screen(*elapsed)
+                    })
                     .inspect(|&item| {
                         let (original, (elapsed, _)) = item;
                         let val = cursor_values.entry(name.clone()).or_insert((f64::MAX, 0.0));
@@ -361,8 +1026,10 @@ impl App {
             .fold(
                 (0, (f64::MAX, f64::MIN), (f64::MAX, f64::MIN)),
                 |(name_len, oacc, sacc), (name, ((omin, omax), (smin, smax)))| {
-                    let val = max_values.entry(name.clone()).or_insert(f64::MIN);
-                    *val = val.max(omax);
+                    if omin <= omax {
+                        let val = max_values.entry(name.clone()).or_insert(f64::MIN);
+                        *val = val.max(omax);
+                    }
 
                     (
                         name_len.max(name.len()),
@@ -372,6 +1039,19 @@ impl App {
                 },
             );
 
+        // The raw lines behind the smoothed ones have to fit as well
+        for (_, (_, set)) in self.panel_signals(panel) {
+            for (raw, (_, scaled)) in set
+                .raw
+                .iter()
+                .zip(set.raw_chart.iter())
+                .filter(|(raw, (elapsed, _))| raw.is_finite() && self.on_screen(*elapsed))
+            {
+                original_min_max = (original_min_max.0.min(*raw), original_min_max.1.max(*raw));
+                scaled_min_max = (scaled_min_max.0.min(*scaled), scaled_min_max.1.max(*scaled));
+            }
+        }
+
         let cursor_points = [
             (cursor_point, scaled_min_max.0),
             (cursor_point, scaled_min_max.1),
@@ -382,7 +1062,20 @@ impl App {
             .map(|(name, (_, val))| (name, val))
             .collect();
 
-        self.chart_bounds = ChartBounds {
+        let sources: BTreeSet<usize> = self
+            .panel_signals(panel)
+            .filter_map(|(_, (_, set))| set.source)
+            .collect();
+        let restart_marks = restarts
+            .iter()
+            .filter(|(source, _)| sources.contains(source))
+            .flat_map(|(_, times)| times)
+            .map(|at| at.saturating_duration_since(self.start_point).as_secs_f64())
+            .filter(|time| self.on_screen(*time))
+            .map(|time| [(time, scaled_min_max.0), (time, scaled_min_max.1)])
+            .collect();
+
+        ChartBounds {
             max_name_len,
             original_min: original_min_max.0,
             original_max: original_min_max.1,
@@ -391,6 +1084,7 @@ impl App {
             max_values,
             label_values,
             cursor_points,
+            restart_marks,
         }
     }
 
@@ -398,51 +1092,124 @@ impl App {
         self.left_border() + self.cursor_position
     }
 
-    pub fn datasets(&self, bounds: &ChartBounds) -> Vec<ChartLine> {
+    pub fn datasets(&self, panel: usize) -> Vec<ChartLine<'_>> {
+        let bounds = &self.chart_bounds[panel];
         let mut sets = Vec::with_capacity(self.signals.len());
         if self.show_cursor {
             sets.push(ChartLine {
                 color_idx: 0,
                 name: "".to_string(),
-                data: self.chart_bounds.cursor_points.as_slice(),
+                segments: vec![bounds.cursor_points.as_slice()],
+                dimmed: false,
+                restart: false,
+            });
+        }
+        if !bounds.restart_marks.is_empty() {
+            sets.push(ChartLine {
+                color_idx: 0,
+                name: "".to_string(),
+                segments: bounds
+                    .restart_marks
+                    .iter()
+                    .map(|mark| mark.as_slice())
+                    .collect(),
+                dimmed: false,
+                restart: true,
             });
         }
+        // The raw lines go first to stay behind the smoothed ones
         sets.extend(
-            self.signals
-                .iter()
-                .enumerate()
-                .filter(|(_, (_, set))| set.chart.iter().any(|v| self.on_screen(v.0)))
-                .map(|(color_idx, (name, set))| {
-                    let curr_val = if self.show_cursor {
-                        bounds
-                            .label_values
-                            .get(name)
-                            .map_or("-".into(), |v| format!("{:.2}", v))
-                    } else {
-                        set.original
-                            .iter()
-                            .zip(set.chart.iter())
-                            .rev()
-                            .find(|(_, (time, _))| self.on_screen(*time))
-                            .map_or("-".into(), |v| format!("{:.2}", v.0))
-                    };
-                    let max_in_window = bounds
-                        .max_values
-                        .get(name)
-                        .map_or("-".into(), |v| format!("{:.2}", v));
-                    let name = format!(
-                        "{name:0$} {1} (max {2})",
-                        bounds.max_name_len, curr_val, max_in_window,
-                    );
-                    ChartLine {
-                        color_idx,
-                        name,
-                        data: set.chart.as_slice(),
-                    }
+            self.panel_signals(panel)
+                .filter(|(_, (_, set))| set.raw_chart.iter().any(|v| self.on_screen(v.0)))
+                .map(|(color_idx, (_, set))| ChartLine {
+                    color_idx,
+                    name: "".to_string(),
+                    segments: set
+                        .raw_chart
+                        .split(|(_, value)| value.is_nan())
+                        .filter(|segment| !segment.is_empty())
+                        .collect(),
+                    dimmed: true,
+                    restart: false,
                 }),
         );
+        let mut lines: Vec<(usize, &str, ChartLine)> = self
+            .panel_signals(panel)
+            .filter(|(_, (_, set))| set.chart.iter().any(|v| self.on_screen(v.0)))
+            .map(|(color_idx, (name, set))| {
+                let (source_idx, source_name) = self.source_label(set.source);
+                let curr_val = if self.show_cursor {
+                    bounds
+                        .label_values
+                        .get(name)
+                        .map_or("-".into(), |v| format!("{:.2}", v))
+                } else {
+                    set.values
+                        .iter()
+                        .zip(set.chart.iter())
+                        .rev()
+                        .find(|(value, (time, _))| value.is_finite() && self.on_screen(*time))
+                        .map_or("-".into(), |v| format!("{:.2}", v.0))
+                };
+                let max_in_window = bounds
+                    .max_values
+                    .get(name)
+                    .map_or("-".into(), |v| format!("{:.2}", v));
+                let transforms: String = self
+                    .pipeline(name)
+                    .iter()
+                    .map(|t| format!(" [{t}]"))
+                    .collect();
+                let name = format!(
+                    "{name:0$} {1} (max {2}){3}",
+                    bounds.max_name_len, curr_val, max_in_window, transforms,
+                );
+                let line = ChartLine {
+                    color_idx,
+                    name,
+                    segments: set
+                        .chart
+                        .split(|(_, value)| value.is_nan())
+                        .filter(|segment| !segment.is_empty())
+                        .collect(),
+                    dimmed: false,
+                    restart: false,
+                };
+                (source_idx, source_name, line)
+            })
+            .collect();
+
+        if self.group_legend {
+            // The source is named on the first line of its group only
+            lines.sort_by_key(|(source_idx, source_name, _)| (*source_idx, *source_name));
+            let width = lines
+                .iter()
+                .map(|(_, name, _)| name.len())
+                .max()
+                .unwrap_or(0);
+            let mut previous = None;
+            for (_, source_name, line) in &mut lines {
+                let source_name = *source_name;
+                let label = if previous == Some(source_name) {
+                    ""
+                } else {
+                    source_name
+                };
+                line.name = format!("{label:width$} {}", line.name);
+                previous = Some(source_name);
+            }
+        }
+        sets.extend(lines.into_iter().map(|(_, _, line)| line));
         sets
     }
+
+    /// Index of the series source and its name, the derived series come last
+    fn source_label(&self, source: Option<usize>) -> (usize, &str) {
+        match source {
+            Some(idx) => (idx, self.source_names.get(idx).map_or("", String::as_str)),
+            None => (usize::MAX, ""),
+        }
+    }
 }
 
 pub fn stdin_reader() -> Box<dyn Iterator<Item = io::Result<String>>> {
@@ -454,41 +1221,57 @@ pub fn file_reader(file: String) -> Box<dyn Iterator<Item = io::Result<String>>>
     Box::new(BufReader::new(f).lines())
 }
 
-fn process_lines_from_iterator<I>(lines: I, start_time: Instant, tx: mpsc::Sender<Signal>)
-where
+fn process_lines_from_iterator<I>(
+    lines: I,
+    context: &str,
+    parser: &LineParser,
+    start_time: Instant,
+    tx: mpsc::Sender<Signal>,
+    reporter: Reporter,
+) where
     I: Iterator<Item = io::Result<String>>,
 {
+    reporter.set_state(SourceState::Running);
     for line in lines {
         let Ok(line) = line else {
             log::error!("ignore input error: {:?}", line);
             continue;
         };
 
-        if !process_metric_line_with_context(&line, "line", start_time, &tx) {
+        if !process_metric_line_with_context(&line, context, parser, start_time, &tx, &reporter) {
             return;
         }
     }
+    reporter.set_state(SourceState::Finished);
 }
 
-pub fn get_input_channel_from_stdin(start_time: Instant) -> io::Result<Receiver<Signal>> {
+pub fn get_input_channel_from_stdin(
+    parser: LineParser,
+    start_time: Instant,
+    health: &Health,
+) -> io::Result<Receiver<Signal>> {
     let (tx, rx) = mpsc::channel();
+    let reporter = health.register("stdin", "stdin");
 
     thread::spawn(move || {
         let lines = stdin_reader();
-        process_lines_from_iterator(lines, start_time, tx);
+        process_lines_from_iterator(lines, "stdin", &parser, start_time, tx, reporter);
     });
     Ok(rx)
 }
 
 pub fn get_input_channel_from_file(
     file: String,
+    parser: LineParser,
     start_time: Instant,
+    health: &Health,
 ) -> io::Result<Receiver<Signal>> {
     let (tx, rx) = mpsc::channel();
+    let reporter = health.register(&file, "file");
 
     thread::spawn(move || {
-        let lines = file_reader(file);
-        process_lines_from_iterator(lines, start_time, tx);
+        let lines = file_reader(file.clone());
+        process_lines_from_iterator(lines, &file, &parser, start_time, tx, reporter);
     });
     Ok(rx)
 }
@@ -503,13 +1286,26 @@ fn is_shell_script(command: &str) -> bool {
         || command.contains('>')
 }
 
-fn parse_command_args(command: &str) -> Result<(String, Vec<String>), String> {
-    if is_shell_script(command) {
+fn parse_command_args(
+    command: &str,
+    mode: ExecMode,
+    shell: &str,
+) -> Result<(String, Vec<String>), String> {
+    let shell_script = match mode {
+        ExecMode::Auto => is_shell_script(command),
+        ExecMode::Shell => true,
+        ExecMode::Exec => false,
+    };
+    if shell_script {
         // Execute as shell script
-        Ok((
-            "sh".to_string(),
-            vec!["-c".to_string(), command.to_string()],
-        ))
+        let mut args = shell_words::split(shell)
+            .map_err(|e| format!("Failed to parse shell '{}': {}", shell, e))?;
+        if args.is_empty() {
+            return Err("Empty shell string".to_string());
+        }
+        let cmd = args.remove(0);
+        args.extend(["-c".to_string(), command.to_string()]);
+        Ok((cmd, args))
     } else {
         // Parse as individual command with arguments
         let parsed_args = shell_words::split(command)
@@ -525,163 +1321,558 @@ fn parse_command_args(command: &str) -> Result<(String, Vec<String>), String> {
     }
 }
 
+/// The program of a source with its arguments, working directory and environment
+struct Launch {
+    cmd: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+}
+
+impl Launch {
+    fn new(spec: &SourceSpec, defaults: &CommandDefaults) -> Result<Self, String> {
+        let mode = spec.mode.unwrap_or(defaults.mode);
+        let shell = spec.shell.as_deref().unwrap_or(&defaults.shell);
+        let (cmd, args) = parse_command_args(&spec.command, mode, shell)?;
+        Ok(Self {
+            cmd,
+            args,
+            cwd: spec.cwd.clone(),
+            env: spec.env.clone(),
+        })
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.cmd);
+        command.args(&self.args).envs(self.env.iter().cloned());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+/// Returns the Unix time in seconds of the `start_time` instant
+pub fn unix_time(start_time: Instant) -> f64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    now - start_time.elapsed().as_secs_f64()
+}
+
+/// Converts a Unix time in seconds to the seconds elapsed since `start_time`
+fn unix_to_elapsed(start_time: Instant, timestamp: f64) -> f64 {
+    timestamp - unix_time(start_time)
+}
+
+/// Returns the default parser with the source specific overrides applied
+fn source_parser(spec: &SourceSpec, default: &LineParser, prefix_sources: bool) -> LineParser {
+    let mut parser = default.clone();
+    if let Some(format) = spec.format {
+        parser.format = format;
+    }
+    parser.prefix = match &spec.alias {
+        Some(alias) => Some(alias.clone()),
+        None if prefix_sources => spec.name.clone(),
+        None => None,
+    };
+    parser
+}
+
 fn process_metric_line_with_context(
     line: &str,
     context: &str,
+    parser: &LineParser,
     start_time: Instant,
     tx: &mpsc::Sender<Signal>,
+    reporter: &Reporter,
 ) -> bool {
-    for metric in line.split(';').filter(|x| !x.is_empty()) {
-        match App::parse_input(metric) {
-            Ok((name, value)) => {
+    // Metrics of the same line share the time
+    let read_time = start_time.elapsed().as_secs_f64();
+    send_signals(
+        parse_signals(line, context, parser, start_time, read_time, reporter),
+        tx,
+    )
+}
+
+/// Parses the line into the signals of the source, the metrics without their
+/// own timestamp are placed at `read_time`
+fn parse_signals(
+    line: &str,
+    context: &str,
+    parser: &LineParser,
+    start_time: Instant,
+    read_time: f64,
+    reporter: &Reporter,
+) -> Vec<Signal> {
+    let mut signals = Vec::new();
+    let mut errors = 0;
+    for metric in parser.parse_line(line) {
+        match metric {
+            Ok(Metric {
+                name,
+                value,
+                timestamp,
+            }) => {
+                let name = match &parser.prefix {
+                    Some(prefix) => format!("{prefix}.{name}"),
+                    None => name,
+                };
+                if !parser.filter.keeps(&name) {
+                    continue;
+                }
                 log::debug!("'{}': {name}={value}", context);
-                let x_time = start_time.elapsed().as_secs_f64();
-                let res = tx.send(Signal {
+                let x_time = match timestamp {
+                    Some(timestamp) => unix_to_elapsed(start_time, timestamp),
+                    None => read_time,
+                };
+                signals.push(Signal {
                     name,
                     x_time,
                     value,
+                    source: reporter.source(),
                 });
-                if res.is_err() {
-                    log::error!("receiver closed? {res:?}");
-                    return false;
-                }
             }
             Err(e) => {
                 log::debug!("ignore parsing err {e} for {line} from '{}'", context);
-                continue;
+                errors += 1;
             }
         }
     }
+    reporter.line(signals.len(), errors);
+    signals
+}
+
+/// Sends the signals, false once the receiver is gone
+fn send_signals(signals: Vec<Signal>, tx: &mpsc::Sender<Signal>) -> bool {
+    for signal in signals {
+        let res = tx.send(signal);
+        if res.is_err() {
+            log::error!("receiver closed? {res:?}");
+            return false;
+        }
+    }
     true
 }
 
+/// Keeps the stderr lines of a source for the health panel, the lines are
+/// parsed for metrics as well when a parser is given
+fn drain_stderr(
+    stderr: impl Read,
+    context: &str,
+    parser: Option<&LineParser>,
+    start_time: Instant,
+    tx: &mpsc::Sender<Signal>,
+    reporter: &Reporter,
+) {
+    for line in BufReader::new(stderr).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to read stderr of '{}': {}", context, e);
+                return;
+            }
+        };
+        reporter.stderr(&line);
+        if let Some(parser) = parser {
+            if !process_metric_line_with_context(&line, context, parser, start_time, tx, reporter) {
+                return;
+            }
+        }
+    }
+}
+
+/// Series about the source itself named `<source>.<metric>`
+fn source_signals(
+    name: &str,
+    source: usize,
+    x_time: f64,
+    metrics: &[(&str, f64)],
+    parser: &LineParser,
+) -> Vec<Signal> {
+    metrics
+        .iter()
+        .map(|(metric, value)| Signal {
+            name: format!("{name}.{metric}"),
+            x_time,
+            value: *value,
+            source,
+        })
+        .filter(|signal| parser.filter.keeps(&signal.name))
+        .collect()
+}
+
 pub fn get_input_channel_from_processes(
-    processes: Vec<String>,
+    processes: Vec<SourceSpec>,
+    defaults: &CommandDefaults,
+    parser: &LineParser,
     start_time: Instant,
     tx: mpsc::Sender<Signal>,
+    health: &Health,
 ) {
-    for process_str in processes {
+    for spec in processes {
         let tx_clone = tx.clone();
         let start_time_clone = start_time;
+        let parse_stderr = spec.parse_stderr.unwrap_or(defaults.parse_stderr);
+        let source_metrics = defaults.source_metrics;
+        let launch = Launch::new(&spec, defaults);
+        let parser = source_parser(&spec, parser, defaults.prefix_sources);
+        let policy = RestartPolicy {
+            max_restarts: spec.max_restarts.or(defaults.restart.max_restarts),
+            ..defaults.restart
+        };
+        let name = spec.name.unwrap_or_else(|| spec.command.clone());
+        let process_str = spec.command;
+        let reporter = health.register(&name, "-p");
 
         thread::spawn(move || {
-            loop {
-                log::info!("Starting process: {}", process_str);
-
-                let (cmd, args) = match parse_command_args(&process_str) {
-                    Ok((cmd, args)) => (cmd, args),
-                    Err(e) => {
-                        log::error!("{}", e);
-                        thread::sleep(Duration::from_secs(5));
-                        continue;
-                    }
-                };
+            let launch = match launch {
+                Ok(launch) => launch,
+                Err(e) => {
+                    log::error!("{}", e);
+                    reporter.exited(SourceState::Failed, e);
+                    return;
+                }
+            };
 
+            let mut restarts = 0;
+            // Runs in a row that ended too soon, every one of them doubles the restart delay
+            let mut short_runs = 0;
+            loop {
+                if restarts > 0 {
+                    reporter.restarted();
+                }
                 log::info!("Starting process: {process_str}");
-                let mut child = match Command::new(&cmd)
-                    .args(&args)
+                let run_start = Instant::now();
+                let succeeded = match launch
+                    .command()
+                    .stdin(Stdio::null())
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
+                    // A group of its own lets the shell pipelines be stopped as a whole
+                    .process_group(0)
                     .spawn()
                 {
-                    Ok(child) => child,
-                    Err(e) => {
-                        log::error!("Failed to spawn process '{}': {}", process_str, e);
-                        thread::sleep(Duration::from_secs(5));
-                        continue;
-                    }
-                };
-
-                // Read from stdout continuously for long-running processes
-                if let Some(stdout) = child.stdout.take() {
-                    let reader = BufReader::new(stdout);
-                    for line in reader.lines() {
-                        let line = match line {
-                            Ok(line) => line,
-                            Err(e) => {
-                                log::error!("Failed to read from process '{}': {}", process_str, e);
-                                break;
+                    Ok(mut child) => {
+                        children::register(child.id());
+                        reporter.set_state(SourceState::Running);
+                        if source_metrics {
+                            let x_time = start_time_clone.elapsed().as_secs_f64();
+                            let metrics = [("restarts", restarts as f64)];
+                            let signals =
+                                source_signals(&name, reporter.source(), x_time, &metrics, &parser);
+                            if !send_signals(signals, &tx_clone) {
+                                return;
                             }
-                        };
+                        }
 
-                        if !process_metric_line_with_context(
-                            &line,
-                            &process_str,
-                            start_time_clone,
-                            &tx_clone,
-                        ) {
-                            return;
+                        // Drain stderr on its own, a full pipe would block the process
+                        if let Some(stderr) = child.stderr.take() {
+                            let context = process_str.clone();
+                            let parser = parse_stderr.then(|| parser.clone());
+                            let tx = tx_clone.clone();
+                            let reporter = reporter.clone();
+                            thread::spawn(move || {
+                                drain_stderr(
+                                    stderr,
+                                    &context,
+                                    parser.as_ref(),
+                                    start_time_clone,
+                                    &tx,
+                                    &reporter,
+                                )
+                            });
                         }
-                    }
-                }
 
-                // Wait for the process to finish
-                match child.wait() {
-                    Ok(status) => {
-                        log::info!("Process '{}' exited with status: {}", process_str, status);
+                        // Read from stdout continuously for long-running processes
+                        if let Some(stdout) = child.stdout.take() {
+                            let reader = BufReader::new(stdout);
+                            for line in reader.lines() {
+                                let line = match line {
+                                    Ok(line) => line,
+                                    Err(e) => {
+                                        log::error!(
+                                            "Failed to read from process '{}': {}",
+                                            process_str,
+                                            e
+                                        );
+                                        break;
+                                    }
+                                };
+
+                                if !process_metric_line_with_context(
+                                    &line,
+                                    &process_str,
+                                    &parser,
+                                    start_time_clone,
+                                    &tx_clone,
+                                    &reporter,
+                                ) {
+                                    return;
+                                }
+                            }
+                        }
+
+                        // Wait for the process to finish
+                        let status = children::wait(&mut child);
+                        match status {
+                            Ok(status) => {
+                                log::info!(
+                                    "Process '{}' exited with status: {}",
+                                    process_str,
+                                    status
+                                );
+                                reporter.exited(SourceState::Restarting, status);
+                                status.success()
+                            }
+                            Err(e) => {
+                                log::error!("Failed to wait for process '{}': {}", process_str, e);
+                                reporter.exited(SourceState::Restarting, e);
+                                false
+                            }
+                        }
                     }
                     Err(e) => {
-                        log::error!("Failed to wait for process '{}': {}", process_str, e);
+                        log::error!("Failed to spawn process '{}': {}", process_str, e);
+                        reporter.exited(SourceState::Restarting, format!("failed to spawn: {e}"));
+                        false
                     }
+                };
+
+                if children::is_stopping() {
+                    return;
+                }
+                if policy.gives_up(restarts) {
+                    log::info!(
+                        "Process '{}' is not restarted after {} restarts",
+                        process_str,
+                        restarts
+                    );
+                    reporter.set_state(if succeeded {
+                        SourceState::Finished
+                    } else {
+                        SourceState::Failed
+                    });
+                    return;
                 }
 
-                // Restart the process after a short delay
-                log::info!("Restarting process '{}' in 1 second...", process_str);
-                thread::sleep(Duration::from_secs(1));
+                if !policy.is_short_run(run_start.elapsed()) {
+                    short_runs = 0;
+                }
+                let delay = policy.backoff(short_runs);
+                short_runs += 1;
+                log::info!("Restarting process '{}' in {:.1?}", process_str, delay);
+                thread::sleep(delay);
+                restarts += 1;
             }
         });
     }
 }
 
+/// Runs the command and collects its output, the command and its children
+/// are killed once the timeout passes and `None` is returned
+fn run_command(command: &mut Command, timeout: Option<Duration>) -> io::Result<Option<Output>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A group of its own lets the shell pipelines be killed as a whole
+        .process_group(0)
+        .spawn()?;
+    let pid = child.id();
+    children::register(pid);
+
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stderr = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut buf = Vec::new();
+                stderr.read_to_end(&mut buf).map(|_| buf)
+            })
+        });
+        let mut stdout = Vec::new();
+        let read = child
+            .stdout
+            .take()
+            .map_or(Ok(0), |mut out| out.read_to_end(&mut stdout));
+        // The child is reaped even if its output can't be read
+        let status = children::wait(&mut child);
+        let stderr = match stderr.map(thread::JoinHandle::join) {
+            Some(Ok(stderr)) => stderr,
+            Some(Err(_)) => Err(io::Error::other("stderr reader panicked")),
+            None => Ok(Vec::new()),
+        };
+        let output = read.and(status).and_then(|status| {
+            Ok(Output {
+                status,
+                stdout,
+                stderr: stderr?,
+            })
+        });
+        let _ = done_tx.send(output);
+    });
+    let output = match timeout {
+        Some(timeout) => match done_rx.recv_timeout(timeout) {
+            Ok(output) => output,
+            Err(_) => {
+                // A no-op when the waiter has just unregistered the group to reap it
+                children::kill(pid);
+                let _ = done_rx.recv();
+                return Ok(None);
+            }
+        },
+        None => done_rx.recv().map_err(io::Error::other)?,
+    };
+    output.map(Some)
+}
+
+/// Runs the commands on a fixed cadence aligned to the start time, their
+/// points are placed at the scheduled time of the run
 pub fn get_input_channel_from_commands(
-    commands: Vec<String>,
-    interval_secs: u64,
+    commands: Vec<SourceSpec>,
+    defaults: &CommandDefaults,
+    parser: &LineParser,
     start_time: Instant,
     tx: mpsc::Sender<Signal>,
+    health: &Health,
 ) {
-    for command_str in commands {
+    for spec in commands {
         let tx_clone = tx.clone();
         let start_time_clone = start_time;
-        let interval = Duration::from_secs(interval_secs);
+        let interval = spec.interval.unwrap_or(defaults.interval);
+        let timeout = spec.timeout.or(defaults.timeout);
+        let overlap = spec.overlap.unwrap_or(defaults.overlap);
+        let parse_stderr = spec.parse_stderr.unwrap_or(defaults.parse_stderr);
+        let source_metrics = defaults.source_metrics;
+        let launch = Launch::new(&spec, defaults);
+        let parser = source_parser(&spec, parser, defaults.prefix_sources);
+        let name = spec.name.unwrap_or_else(|| spec.command.clone());
+        let command_str = spec.command;
+        let reporter = health.register(&name, "-c");
 
         thread::spawn(move || {
+            let launch = match launch {
+                Ok(launch) => launch,
+                Err(e) => {
+                    log::error!("{}", e);
+                    reporter.exited(SourceState::Failed, e);
+                    return;
+                }
+            };
+
+            // Series of the command, a timed out run leaves a gap in them
+            let mut names = BTreeSet::new();
+            let mut slot = None;
             loop {
+                let current = match slot {
+                    Some(prev) => overlap.next_slot(prev, interval, start_time_clone.elapsed()),
+                    None => 0,
+                };
+                slot = Some(current);
+                let scheduled = interval.mul_f64(current as f64);
+                if let Some(wait) = scheduled.checked_sub(start_time_clone.elapsed()) {
+                    thread::sleep(wait);
+                }
+                if children::is_stopping() {
+                    return;
+                }
+                let x_time = scheduled.as_secs_f64();
+
                 log::info!("Executing command: {}", command_str);
 
-                let (cmd, args) = match parse_command_args(&command_str) {
-                    Ok((cmd, args)) => (cmd, args),
-                    Err(e) => {
-                        log::error!("{}", e);
-                        thread::sleep(interval);
+                // Spawn the command and wait for it to complete
+                let run_start = Instant::now();
+                let output = match run_command(&mut launch.command(), timeout) {
+                    Ok(Some(output)) => output,
+                    Ok(None) => {
+                        log::warn!("Command '{}' timed out and was killed", command_str);
+                        reporter.exited(SourceState::Failed, "timed out");
+                        let mut gap: Vec<Signal> = names
+                            .iter()
+                            .map(|name| Signal {
+                                name: String::clone(name),
+                                x_time,
+                                value: f64::NAN,
+                                source: reporter.source(),
+                            })
+                            .collect();
+                        if source_metrics {
+                            let metrics = [
+                                ("duration_s", run_start.elapsed().as_secs_f64()),
+                                ("exit_code", f64::NAN),
+                                ("lines", f64::NAN),
+                            ];
+                            gap.extend(source_signals(
+                                &name,
+                                reporter.source(),
+                                x_time,
+                                &metrics,
+                                &parser,
+                            ));
+                        }
+                        if !send_signals(gap, &tx_clone) {
+                            return;
+                        }
                         continue;
                     }
-                };
-
-                // Spawn the command and wait for it to complete
-                let output = match Command::new(&cmd).args(&args).output() {
-                    Ok(output) => output,
                     Err(e) => {
                         log::error!("Failed to execute command '{}': {}", command_str, e);
-                        thread::sleep(interval);
+                        reporter.exited(SourceState::Failed, format!("failed to spawn: {e}"));
                         continue;
                     }
                 };
 
+                let duration = run_start.elapsed().as_secs_f64();
+
                 // Process the output
                 let stdout_str = String::from_utf8_lossy(&output.stdout);
-                for line in stdout_str.lines() {
-                    if !process_metric_line_with_context(
+                let stderr_str = String::from_utf8_lossy(&output.stderr);
+                let parsed_stderr = if parse_stderr {
+                    stderr_str.lines()
+                } else {
+                    "".lines()
+                };
+                for line in stdout_str.lines().chain(parsed_stderr) {
+                    let signals = parse_signals(
                         line,
                         &command_str,
+                        &parser,
                         start_time_clone,
-                        &tx_clone,
-                    ) {
+                        x_time,
+                        &reporter,
+                    );
+                    names.extend(signals.iter().map(|signal| signal.name.clone()));
+                    if !send_signals(signals, &tx_clone) {
+                        return;
+                    }
+                }
+
+                if source_metrics {
+                    let status = &output.status;
+                    let exit_code = status.code().or(status.signal().map(|sig| 128 + sig));
+                    let metrics = [
+                        ("duration_s", duration),
+                        ("exit_code", exit_code.map_or(f64::NAN, f64::from)),
+                        ("lines", stdout_str.lines().count() as f64),
+                    ];
+                    let signals =
+                        source_signals(&name, reporter.source(), x_time, &metrics, &parser);
+                    if !send_signals(signals, &tx_clone) {
                         return;
                     }
                 }
 
+                for line in stderr_str.lines() {
+                    reporter.stderr(line);
+                }
+                let state = if output.status.success() {
+                    SourceState::Running
+                } else {
+                    SourceState::Failed
+                };
+                reporter.exited(state, output.status);
                 if !output.status.success() {
-                    let stderr_str = String::from_utf8_lossy(&output.stderr);
                     log::warn!(
                         "Command '{}' failed with status {}: {}",
                         command_str,
@@ -689,33 +1880,107 @@ pub fn get_input_channel_from_commands(
                         stderr_str
                     );
                 }
-
-                // Wait for the specified interval before running again
-                thread::sleep(interval);
             }
         });
     }
 }
 
 pub fn get_input_channel_from_processes_and_commands(
-    processes: Vec<String>,
-    commands: Vec<String>,
-    interval_secs: u64,
+    processes: Vec<SourceSpec>,
+    commands: Vec<SourceSpec>,
+    defaults: CommandDefaults,
+    parser: LineParser,
     start_time: Instant,
+    health: &Health,
 ) -> io::Result<Receiver<Signal>> {
     let (tx, rx) = mpsc::channel();
 
     // Handle long-running processes
     if !processes.is_empty() {
-        get_input_channel_from_processes(processes, start_time, tx.clone());
+        get_input_channel_from_processes(
+            processes,
+            &defaults,
+            &parser,
+            start_time,
+            tx.clone(),
+            health,
+        );
     }
 
     // Handle interval-based commands
     if !commands.is_empty() {
-        get_input_channel_from_commands(commands, interval_secs, start_time, tx.clone());
+        get_input_channel_from_commands(
+            commands,
+            &defaults,
+            &parser,
+            start_time,
+            tx.clone(),
+            health,
+        );
     }
 
     // Drop the original sender so the channel closes when all threads finish
     drop(tx);
     Ok(rx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app with a rate of `bytes` and `kib` derived from it
+    fn app(points: &[(f64, f64)]) -> App {
+        let (tx, rx) = mpsc::channel();
+        for &(x_time, value) in points {
+            let name = "bytes".to_string();
+            tx.send(Signal {
+                name,
+                x_time,
+                value,
+                source: 0,
+            })
+            .unwrap();
+        }
+        let mut app = App::new(rx, Instant::now());
+        app.transforms.add("bytes".to_string(), Transform::Rate);
+        app.exprs = vec!["kib = bytes / 1024".parse().unwrap()];
+        app.on_tick();
+        app
+    }
+
+    fn last(app: &App, name: &str) -> f64 {
+        *app.signals[name].values.last().unwrap()
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_series_key_event(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn series_list_toggles_the_transforms_of_the_selected_series() {
+        let mut app = app(&[(0.0, 0.0), (1.0, 1024.0), (2.0, 3072.0)]);
+        assert_eq!(last(&app, "bytes"), 2048.0);
+
+        // The series are listed by name, `bytes` first
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(last(&app, "bytes"), 3072.0);
+        assert_eq!(app.series_list()[0].3, "rate, off");
+
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(last(&app, "bytes"), 2048.0);
+        assert_eq!(app.series_list()[0].3, "rate");
+    }
+
+    #[test]
+    fn derived_series_follow_the_toggled_transforms() {
+        let mut app = app(&[(0.0, 0.0), (1.0, 1024.0), (2.0, 3072.0)]);
+        assert_eq!(last(&app, "kib"), 2.0);
+
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(last(&app, "kib"), 3.0);
+        assert_eq!(app.signals["kib"].values.len(), 3);
+
+        press(&mut app, KeyCode::Char('r'));
+        assert_eq!(last(&app, "kib"), 2.0);
+    }
+}
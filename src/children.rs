@@ -0,0 +1,170 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    process::{self, Child, ExitStatus},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::term;
+
+/// How long the children have to exit after SIGTERM before they are killed
+const GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Process groups of the running `-p`/`-c` children, every child leads a group of its own.
+/// A group is only signalled while it is registered and it is unregistered
+/// before its leader is reaped, so its id can't have been reused by then.
+struct Registry {
+    groups: BTreeSet<libc::pid_t>,
+    stopping: bool,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    groups: BTreeSet::new(),
+    stopping: false,
+});
+
+/// The first SIGHUP, SIGINT or SIGTERM received, zero until then
+static INTERRUPTED: AtomicI32 = AtomicI32::new(0);
+
+fn lock() -> MutexGuard<'static, Registry> {
+    // The registry is still needed to stop the children after a panic
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn signal(group: libc::pid_t, signal: libc::c_int) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(-group, signal) == 0 }
+}
+
+/// Tracks the group led by the child until it is reaped, a child spawned
+/// during the shutdown is asked to terminate right away
+pub fn register(pid: u32) {
+    let group = pid as libc::pid_t;
+    let mut registry = lock();
+    if registry.stopping {
+        signal(group, libc::SIGTERM);
+    }
+    registry.groups.insert(group);
+}
+
+fn unregister(pid: u32) {
+    lock().groups.remove(&(pid as libc::pid_t));
+}
+
+/// Waits for the registered child to exit, unregisters its group and reaps it
+pub fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+    let pid = child.id();
+    // The exited leader stays a zombie holding its pid until it is reaped
+    loop {
+        // SAFETY: an all-zero siginfo_t is valid, waitid only writes to it
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: the pointer is to a live siginfo_t
+        let res = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if res == 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            unregister(pid);
+            return Err(err);
+        }
+    }
+    unregister(pid);
+    child.wait()
+}
+
+/// Kills the group of the child unless it is already unregistered
+pub fn kill(pid: u32) {
+    let registry = lock();
+    let group = pid as libc::pid_t;
+    if registry.groups.contains(&group) {
+        signal(group, libc::SIGKILL);
+    }
+}
+
+/// Whether the children are being stopped, no new ones should be started
+pub fn is_stopping() -> bool {
+    lock().stopping
+}
+
+/// Sends SIGTERM to the groups of the children and SIGKILL to the ones
+/// still running after the grace period
+pub fn shutdown() {
+    {
+        let mut registry = lock();
+        registry.stopping = true;
+        for &group in &registry.groups {
+            signal(group, libc::SIGTERM);
+        }
+    }
+
+    let deadline = Instant::now() + GRACE_PERIOD;
+    loop {
+        let registry = lock();
+        let mut alive = registry
+            .groups
+            .iter()
+            .copied()
+            .filter(|&group| signal(group, 0))
+            .peekable();
+        if alive.peek().is_none() {
+            return;
+        }
+        if Instant::now() >= deadline {
+            for group in alive {
+                log::warn!("killing process group {group} that ignored SIGTERM");
+                signal(group, libc::SIGKILL);
+            }
+            return;
+        }
+        drop(registry);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Handles SIGHUP, SIGINT and SIGTERM so that the children are stopped when tlook is.
+/// SIGINT and SIGTERM ask the main loop to exit, a repeated one stops the children and exits
+/// right away in case the main loop is stuck. After SIGHUP the terminal is gone and reading
+/// it never returns, so the children are stopped and tlook exits right away.
+pub fn stop_on_signals() -> io::Result<()> {
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    thread::spawn(move || {
+        for sig in signals.forever() {
+            let first = INTERRUPTED
+                .compare_exchange(0, sig, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok();
+            if sig == SIGHUP || !first {
+                // Like the panic hook, the terminal may be gone already
+                let _ = term::restore();
+                shutdown();
+                process::exit(128 + sig);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// The signal that asked tlook to exit, if any
+pub fn interrupted() -> Option<libc::c_int> {
+    match INTERRUPTED.load(Ordering::Relaxed) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
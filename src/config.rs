@@ -0,0 +1,407 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use ratatui::style::Color;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::app::ChartScale;
+use crate::duration::parse_duration;
+use crate::expr::Derived;
+use crate::format::InputFormat;
+use crate::panel::{Layout, Panel};
+use crate::source::{ExecMode, Overlap, SourceSpec};
+use crate::transform::{self, Rule};
+
+/// Settings of a profile, every one of them is optional.
+///
+/// The top-level settings of the config file apply to all the profiles and
+/// a `[profiles.<name>]` table overrides them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    #[serde(deserialize_with = "sources")]
+    pub processes: Option<Vec<SourceSpec>>,
+    #[serde(deserialize_with = "sources")]
+    pub commands: Option<Vec<SourceSpec>>,
+    #[serde(deserialize_with = "interval")]
+    pub interval: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    #[serde(deserialize_with = "value_enum")]
+    pub overlap: Option<Overlap>,
+    pub parse_stderr: Option<bool>,
+    pub source_metrics: Option<bool>,
+    #[serde(deserialize_with = "duration")]
+    pub restart_delay: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub restart_max_delay: Option<Duration>,
+    pub max_restarts: Option<u64>,
+    pub once: Option<bool>,
+    #[serde(deserialize_with = "value_enum")]
+    pub exec_mode: Option<ExecMode>,
+    pub shell: Option<String>,
+    pub prefix_sources: Option<bool>,
+    #[serde(deserialize_with = "value_enum")]
+    pub format: Option<InputFormat>,
+    #[serde(deserialize_with = "duration")]
+    pub window: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub history: Option<Duration>,
+    #[serde(deserialize_with = "duration")]
+    pub tick_rate: Option<Duration>,
+    #[serde(deserialize_with = "value_enum")]
+    pub scale: Option<ChartScale>,
+    pub legend: Option<bool>,
+    pub axis_labels: Option<bool>,
+    pub cursor: Option<bool>,
+    pub group_legend: Option<bool>,
+    /// Series colors as names, `#rrggbb` or indexes of the terminal palette
+    #[serde(deserialize_with = "parsed")]
+    pub colors: Option<Vec<Color>>,
+    pub rate: Option<Vec<String>>,
+    #[serde(deserialize_with = "sma")]
+    pub sma: Option<Vec<Rule>>,
+    #[serde(deserialize_with = "ewma")]
+    pub ewma: Option<Vec<Rule>>,
+    #[serde(deserialize_with = "median")]
+    pub median: Option<Vec<Rule>>,
+    pub show_raw: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    pub expr: Option<Vec<Derived>>,
+    #[serde(deserialize_with = "parsed")]
+    pub include: Option<Vec<Regex>>,
+    #[serde(deserialize_with = "parsed")]
+    pub exclude: Option<Vec<Regex>>,
+    #[serde(deserialize_with = "parsed")]
+    pub panels: Option<Vec<Panel>>,
+    #[serde(deserialize_with = "value_enum")]
+    pub layout: Option<Layout>,
+}
+
+impl Profile {
+    /// The settings of the profile, the ones it lacks are taken from the base
+    fn or(self, base: Profile) -> Profile {
+        Profile {
+            processes: self.processes.or(base.processes),
+            commands: self.commands.or(base.commands),
+            interval: self.interval.or(base.interval),
+            timeout: self.timeout.or(base.timeout),
+            overlap: self.overlap.or(base.overlap),
+            parse_stderr: self.parse_stderr.or(base.parse_stderr),
+            source_metrics: self.source_metrics.or(base.source_metrics),
+            restart_delay: self.restart_delay.or(base.restart_delay),
+            restart_max_delay: self.restart_max_delay.or(base.restart_max_delay),
+            max_restarts: self.max_restarts.or(base.max_restarts),
+            once: self.once.or(base.once),
+            exec_mode: self.exec_mode.or(base.exec_mode),
+            shell: self.shell.or(base.shell),
+            prefix_sources: self.prefix_sources.or(base.prefix_sources),
+            format: self.format.or(base.format),
+            window: self.window.or(base.window),
+            history: self.history.or(base.history),
+            tick_rate: self.tick_rate.or(base.tick_rate),
+            scale: self.scale.or(base.scale),
+            legend: self.legend.or(base.legend),
+            axis_labels: self.axis_labels.or(base.axis_labels),
+            cursor: self.cursor.or(base.cursor),
+            group_legend: self.group_legend.or(base.group_legend),
+            colors: self.colors.or(base.colors),
+            rate: self.rate.or(base.rate),
+            sma: self.sma.or(base.sma),
+            ewma: self.ewma.or(base.ewma),
+            median: self.median.or(base.median),
+            show_raw: self.show_raw.or(base.show_raw),
+            expr: self.expr.or(base.expr),
+            include: self.include.or(base.include),
+            exclude: self.exclude.or(base.exclude),
+            panels: self.panels.or(base.panels),
+            layout: self.layout.or(base.layout),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/tlook/config.toml` or `~/.config/tlook/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("tlook").join("config.toml"))
+}
+
+/// Loads the profile from the config file, the default file may be missing
+/// unless a profile is asked for
+pub fn load(path: Option<&str>, profile: Option<&str>) -> Result<Profile> {
+    let (path, explicit) = match path {
+        Some(path) => (PathBuf::from(path), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Profile::default()),
+        },
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit && profile.is_none() => {
+            return Ok(Profile::default());
+        }
+        Err(e) => return Err(e).wrap_err_with(|| format!("failed to read {}", path.display())),
+    };
+
+    parse(&text, profile).wrap_err_with(|| format!("invalid config {}", path.display()))
+}
+
+fn parse(text: &str, profile: Option<&str>) -> Result<Profile> {
+    let mut table: toml::Table = toml::from_str(text)?;
+    let mut profiles: BTreeMap<String, Profile> = match table.remove("profiles") {
+        Some(profiles) => profiles.try_into()?,
+        None => BTreeMap::new(),
+    };
+    let defaults: Profile = table.try_into()?;
+
+    let Some(name) = profile else {
+        return Ok(defaults);
+    };
+    let selected = profiles.remove(name).ok_or_else(|| {
+        let known: Vec<&String> = profiles.keys().collect();
+        eyre!("unknown profile {name:?}, the known ones are {known:?}")
+    })?;
+    Ok(selected.or(defaults))
+}
+
+/// A list of values parsed with their `FromStr`
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let values: Vec<String> = Vec::deserialize(deserializer)?;
+    values
+        .iter()
+        .map(|value| value.parse().map_err(de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn rules<'de, D>(
+    deserializer: D,
+    parse: fn(&str) -> Result<Rule, String>,
+) -> Result<Option<Vec<Rule>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values: Vec<String> = Vec::deserialize(deserializer)?;
+    values
+        .iter()
+        .map(|value| parse(value).map_err(de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn sma<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Rule>>, D::Error> {
+    rules(deserializer, transform::parse_sma)
+}
+
+fn ewma<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Rule>>, D::Error> {
+    rules(deserializer, transform::parse_ewma)
+}
+
+fn median<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Rule>>, D::Error> {
+    rules(deserializer, transform::parse_median)
+}
+
+fn value_enum<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: ValueEnum,
+{
+    let value = String::deserialize(deserializer)?;
+    T::from_str(&value, true)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+/// A duration is either a number of seconds or a human duration like `5m`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DurationValue {
+    Seconds(f64),
+    Text(String),
+}
+
+impl DurationValue {
+    fn parse<E: de::Error>(self) -> Result<Duration, E> {
+        match self {
+            DurationValue::Seconds(secs) => Duration::try_from_secs_f64(secs)
+                .map_err(|_| de::Error::custom(format!("invalid duration {secs}"))),
+            DurationValue::Text(text) => parse_duration(&text).map_err(de::Error::custom),
+        }
+    }
+
+    fn parse_interval<E: de::Error>(self) -> Result<Duration, E> {
+        match self.parse()? {
+            interval if interval.is_zero() => {
+                Err(de::Error::custom("the interval has to be positive"))
+            }
+            interval => Ok(interval),
+        }
+    }
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    DurationValue::deserialize(deserializer)?.parse().map(Some)
+}
+
+fn interval<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    DurationValue::deserialize(deserializer)?
+        .parse_interval()
+        .map(Some)
+}
+
+/// A source is either a `-p`/`-c` like string or a table with the command and its options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Source {
+    Spec(String),
+    Table(Box<SourceTable>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceTable {
+    command: String,
+    /// The alias prefixing the series of the source
+    name: Option<String>,
+    format: Option<String>,
+    interval: Option<DurationValue>,
+    timeout: Option<DurationValue>,
+    overlap: Option<String>,
+    parse_stderr: Option<bool>,
+    max_restarts: Option<u64>,
+    mode: Option<String>,
+    shell: Option<String>,
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+fn sources<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<SourceSpec>>, D::Error> {
+    let sources: Vec<Source> = Vec::deserialize(deserializer)?;
+    sources
+        .into_iter()
+        .map(|source| match source {
+            Source::Spec(spec) => spec.parse().map_err(de::Error::custom),
+            Source::Table(table) => {
+                let SourceTable {
+                    command,
+                    name,
+                    format,
+                    interval,
+                    timeout,
+                    overlap,
+                    parse_stderr,
+                    max_restarts,
+                    mode,
+                    shell,
+                    cwd,
+                    env,
+                } = *table;
+                Ok(SourceSpec {
+                    command,
+                    alias: name,
+                    name: None,
+                    format: format
+                        .map(|format| InputFormat::from_str(&format, true))
+                        .transpose()
+                        .map_err(de::Error::custom)?,
+                    interval: interval.map(DurationValue::parse_interval).transpose()?,
+                    timeout: timeout.map(DurationValue::parse).transpose()?,
+                    overlap: overlap
+                        .map(|overlap| Overlap::from_str(&overlap, true))
+                        .transpose()
+                        .map_err(de::Error::custom)?,
+                    parse_stderr,
+                    max_restarts,
+                    mode: mode
+                        .map(|mode| ExecMode::from_str(&mode, true))
+                        .transpose()
+                        .map_err(de::Error::custom)?,
+                    shell,
+                    cwd,
+                    env: env.into_iter().collect(),
+                })
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+interval = "5s"
+legend = false
+commands = ["df -h /"]
+
+[profiles.net]
+interval = 10
+scale = "asinh"
+commands = [
+    "2s:ping -c 1 host",
+    { command = "ss -s", name = "sockets", interval = "1m", timeout = "5s" },
+]
+"#;
+
+    #[test]
+    fn profile_overrides_the_top_level_settings() {
+        let profile = parse(CONFIG, Some("net")).unwrap();
+        assert_eq!(profile.interval, Some(Duration::from_secs(10)));
+        assert_eq!(profile.scale, Some(ChartScale::Asinh));
+        // Settings the profile lacks come from the top level
+        assert_eq!(profile.legend, Some(false));
+
+        let defaults = parse(CONFIG, None).unwrap();
+        assert_eq!(defaults.interval, Some(Duration::from_secs(5)));
+        assert_eq!(defaults.scale, None);
+        assert_eq!(defaults.commands.unwrap()[0].command, "df -h /");
+    }
+
+    #[test]
+    fn sources_take_their_own_intervals() {
+        let commands = parse(CONFIG, Some("net")).unwrap().commands.unwrap();
+        assert_eq!(commands[0].command, "ping -c 1 host");
+        assert_eq!(commands[0].interval, Some(Duration::from_secs(2)));
+        assert_eq!(commands[1].alias.as_deref(), Some("sockets"));
+        assert_eq!(commands[1].interval, Some(Duration::from_secs(60)));
+        assert_eq!(commands[1].timeout, Some(Duration::from_secs(5)));
+
+        let zero = r#"commands = [{ command = "ss -s", interval = "0s" }]"#;
+        assert!(parse(zero, None).is_err());
+    }
+
+    #[test]
+    fn unknown_profiles_are_errors() {
+        let err = parse(CONFIG, Some("disk")).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown profile \"disk\""),
+            "{err}"
+        );
+        assert!(parse("", Some("net")).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert!(parse("intervall = 5", None).is_err());
+        assert!(parse("[profiles.net]\nlegnd = true", Some("net")).is_err());
+        assert!(parse("commands = [{ command = \"ls\", every = 5 }]", None).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+/// Parses a human duration like `250ms`, `30s`, `5m`, `2h`, `1d` or `1h30m`,
+/// a plain number is taken as seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {value:?}, expected e.g. 500ms, 30s, 5m or 2h");
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|_| invalid());
+    }
+
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(invalid)?;
+        let unit_end = rest[number_end..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .map_or(rest.len(), |end| number_end + end);
+        let number: f64 = rest[..number_end].parse().map_err(|_| invalid())?;
+        let unit = match &rest[number_end..unit_end] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return Err(invalid()),
+        };
+        total += number * unit;
+        rest = &rest[unit_end..];
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| invalid())
+}
+
+/// Parses the interval of the `-c` commands or the `--expr-bucket` width, a zero interval
+/// would run the commands back to back
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        interval if interval.is_zero() => {
+            Err(format!("the duration has to be positive, got {value:?}"))
+        }
+        interval => Ok(interval),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("100ms"), Ok(Duration::from_millis(100)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn bare_numbers_are_seconds() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("0.25"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration(" 2 "), Ok(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn invalid_durations_are_errors() {
+        for value in [
+            "", " ", "-1", "-5s", "5x", "5 m", "m", "ms5", "1.2.3s", "inf", "NaN",
+        ] {
+            assert!(parse_duration(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn intervals_are_positive() {
+        assert_eq!(parse_interval("1s"), Ok(Duration::from_secs(1)));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("0ms").is_err());
+    }
+}
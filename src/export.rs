@@ -0,0 +1,179 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A row per timestamp and a column per series
+    #[default]
+    Csv,
+    /// A `time,name,value` row per point
+    CsvLong,
+    /// An object with the `[time, value]` points of every series
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv | ExportFormat::CsvLong => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ExportRange {
+    /// The points visible in the chart window
+    Window,
+    /// The whole retained history
+    #[default]
+    All,
+}
+
+/// Points of a series as `(unix time, original value)` pairs
+pub type Series<'a> = (&'a str, Vec<(f64, f64)>);
+
+pub fn export(path: &str, format: ExportFormat, series: &[Series]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => write_wide_csv(&mut out, series)?,
+        ExportFormat::CsvLong => write_long_csv(&mut out, series)?,
+        ExportFormat::Json => write_json(&mut out, series)?,
+    }
+    out.flush()
+}
+
+/// A time ordered by `f64::total_cmp`, times before 1970 are negative
+#[derive(Debug, Copy, Clone)]
+struct Time(f64);
+
+impl PartialEq for Time {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Time {}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+fn write_wide_csv(out: &mut impl Write, series: &[Series]) -> io::Result<()> {
+    // Points are aligned by the exact time
+    let mut rows: BTreeMap<Time, Vec<Option<f64>>> = BTreeMap::new();
+    for (idx, (_, points)) in series.iter().enumerate() {
+        for &(time, value) in points {
+            let row = rows
+                .entry(Time(time))
+                .or_insert_with(|| vec![None; series.len()]);
+            row[idx] = Some(value);
+        }
+    }
+
+    write!(out, "time")?;
+    for (name, _) in series {
+        write!(out, ",{}", csv_field(name))?;
+    }
+    writeln!(out)?;
+    for (Time(time), row) in rows {
+        write!(out, "{time:.6}")?;
+        for value in row {
+            match value {
+                Some(value) => write!(out, ",{value}")?,
+                None => write!(out, ",")?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_long_csv(out: &mut impl Write, series: &[Series]) -> io::Result<()> {
+    writeln!(out, "time,name,value")?;
+    for (name, points) in series {
+        let name = csv_field(name);
+        for (time, value) in points {
+            writeln!(out, "{time:.6},{name},{value}")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, series: &[Series]) -> io::Result<()> {
+    let series: Map<String, Value> = series
+        .iter()
+        .map(|(name, points)| {
+            // Non-finite values have no JSON representation and become null
+            let points = points
+                .iter()
+                .map(|&(time, value)| json!([time, value]))
+                .collect();
+            (name.to_string(), Value::Array(points))
+        })
+        .collect();
+    serde_json::to_writer(&mut *out, &json!({ "series": series }))?;
+    writeln!(out)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide_csv(series: &[Series]) -> String {
+        let mut out = Vec::new();
+        write_wide_csv(&mut out, series).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn wide_csv_aligns_points_by_time() {
+        let series = [
+            ("a", vec![(1.0, 10.0), (2.0, 20.0)]),
+            ("b", vec![(2.0, 5.0)]),
+        ];
+        assert_eq!(wide_csv(&series), "time,a,b\n1.000000,10,\n2.000000,20,5\n");
+    }
+
+    #[test]
+    fn wide_csv_orders_times_before_1970() {
+        let series = [
+            ("a", vec![(-86400.0, 1.0), (0.0, 2.0), (86400.0, 3.0)]),
+            ("b", vec![(-1.5, 4.0), (86400.0, 5.0)]),
+        ];
+        assert_eq!(
+            wide_csv(&series),
+            "time,a,b\n-86400.000000,1,\n-1.500000,,4\n0.000000,2,\n86400.000000,3,5\n"
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("plain"), "plain");
+    }
+}
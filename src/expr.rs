@@ -0,0 +1,258 @@
+use std::{iter::Peekable, str::Chars, str::FromStr};
+
+/// An arithmetic expression over series values
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, lookup: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Expr::Num(value) => *value,
+            Expr::Var(name) => lookup(name)?,
+            Expr::Neg(expr) => -expr.eval(lookup)?,
+            Expr::Bin(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                }
+            }
+        })
+    }
+
+    fn vars<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Num(_) => {}
+            Expr::Var(name) => out.push(name),
+            Expr::Neg(expr) => expr.vars(out),
+            Expr::Bin(_, lhs, rhs) => {
+                lhs.vars(out);
+                rhs.vars(out);
+            }
+        }
+    }
+}
+
+/// A computed series like `used_pct = used / total * 100`.
+///
+/// Series names are made of letters, digits, `_`, `.` and `:`, any other
+/// name can be quoted with backticks, e.g. `` `input-a` * 2 ``.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derived {
+    pub name: String,
+    expr: Expr,
+}
+
+impl Derived {
+    /// Names of the series the expression depends on
+    pub fn vars(&self) -> Vec<&str> {
+        let mut vars = Vec::new();
+        self.expr.vars(&mut vars);
+        vars.sort_unstable();
+        vars.dedup();
+        vars
+    }
+
+    /// Evaluates the expression, `None` if any of the series has no value
+    pub fn eval(&self, lookup: impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        self.expr.eval(&lookup)
+    }
+}
+
+impl FromStr for Derived {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, expr)) = s.split_once('=') else {
+            return Err(format!("expected 'name = expression', got {s:?}"));
+        };
+        let name = name.trim();
+        let name = match name.strip_prefix('`') {
+            Some(quoted) => quoted
+                .strip_suffix('`')
+                .ok_or_else(|| format!("unterminated '`' in {s:?}"))?,
+            None => name,
+        };
+        if name.is_empty() {
+            return Err(format!("missing series name in {s:?}"));
+        }
+
+        let mut parser = Parser {
+            chars: expr.chars().peekable(),
+        };
+        let expr = parser.expr()?;
+        if let Some(c) = parser.next_token() {
+            return Err(format!("unexpected {c:?} in {s:?}"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expr,
+        })
+    }
+}
+
+/// Recursive descent parser of `+ - * /`, unary minus and parentheses
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn next_token(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.next_token() {
+            self.chars.next();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.next_token() {
+            self.chars.next();
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.factor()?));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next_token() {
+            Some('-') => {
+                self.chars.next();
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let expr = self.expr()?;
+                match self.next_token() {
+                    Some(')') => {
+                        self.chars.next();
+                        Ok(expr)
+                    }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some('`') => {
+                self.chars.next();
+                let name = self.take_while(|c| c != '`');
+                match self.chars.next() {
+                    Some(_) if name.is_empty() => Err("empty series name '``'".to_string()),
+                    Some(_) => Ok(Expr::Var(name)),
+                    None => Err(format!("unterminated '`' before {name:?}")),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                number
+                    .parse()
+                    .map(Expr::Num)
+                    .map_err(|e| format!("invalid number {number:?}: {e}"))
+            }
+            Some(c) if is_name_char(c) => Ok(Expr::Var(self.take_while(is_name_char))),
+            Some(c) => Err(format!("unexpected {c:?}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let mut out = String::new();
+        while let Some(c) = self.chars.next_if(|&c| accept(c)) {
+            out.push(c);
+        }
+        out
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Option<f64> {
+        let derived: Derived = format!("x = {expr}").parse().unwrap();
+        derived.eval(|name| match name {
+            "a" => Some(2.0),
+            "b" => Some(3.0),
+            "input-a" => Some(10.0),
+            "cpu.user{host=\"a\"}" => Some(4.0),
+            _ => None,
+        })
+    }
+
+    fn error(value: &str) -> String {
+        value.parse::<Derived>().unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Some(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Some(9.0));
+        assert_eq!(eval("8 / 4 / 2"), Some(1.0));
+        assert_eq!(eval("10 - 4 - 3"), Some(3.0));
+        assert_eq!(eval("a * b + a / 4"), Some(6.5));
+        assert_eq!(eval("a*(b-1)"), Some(4.0));
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-a"), Some(-2.0));
+        assert_eq!(eval("--a"), Some(2.0));
+        assert_eq!(eval("-a * b"), Some(-6.0));
+        assert_eq!(eval("b - -a"), Some(5.0));
+        assert_eq!(eval("-(a + b)"), Some(-5.0));
+    }
+
+    #[test]
+    fn backtick_names() {
+        assert_eq!(eval("`input-a` * 2"), Some(20.0));
+        assert_eq!(eval("`cpu.user{host=\"a\"}` + a"), Some(6.0));
+
+        let derived: Derived = "`used %` = a".parse().unwrap();
+        assert_eq!(derived.name, "used %");
+        let derived: Derived = "sum = `input-a` + a + `input-a`".parse().unwrap();
+        assert_eq!(derived.vars(), ["a", "input-a"]);
+    }
+
+    #[test]
+    fn missing_series() {
+        assert_eq!(eval("a + unknown"), None);
+    }
+
+    #[test]
+    fn trailing_garbage() {
+        assert!(error("x = a b").contains("unexpected 'b'"));
+        assert!(error("x = a + 1)").contains("unexpected ')'"));
+        assert!(error("x = (a + 1").contains("missing ')'"));
+        assert!(error("x = a +").contains("unexpected end"));
+        assert!(error("x = 1.2.3").contains("invalid number"));
+    }
+
+    #[test]
+    fn unterminated_backtick() {
+        assert!(error("x = `input-a * 2").contains("unterminated"));
+        assert!(error("x = a + `").contains("unterminated"));
+        assert!(error("`x = a").contains("unterminated"));
+        assert!(error("x = ``").contains("empty series name"));
+    }
+
+    #[test]
+    fn missing_name() {
+        assert!(error("a + b").contains("expected 'name = expression'"));
+        assert!(error(" = a").contains("missing series name"));
+    }
+}
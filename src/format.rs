@@ -0,0 +1,665 @@
+use clap::ValueEnum;
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result,
+};
+use serde_json::Value;
+
+use crate::pattern::{glob_match, NameFilter};
+
+/// The syntax of input lines produced by a source
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// `name=value` pairs separated by `;`
+    #[default]
+    Kv,
+    /// One JSON object per line, numeric fields become series
+    Json,
+    /// Prometheus/OpenMetrics text exposition format
+    #[value(alias = "prometheus", alias = "openmetrics")]
+    Prom,
+    /// InfluxDB line protocol, every field becomes a `measurement.field{tags}` series
+    #[value(alias = "line-protocol")]
+    Influx,
+}
+
+/// Unit of the numeric timestamps carried by the input
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TimeUnit {
+    /// Guess the unit from the magnitude of the value
+    #[default]
+    Auto,
+    S,
+    Ms,
+    Us,
+    Ns,
+}
+
+/// Suffixes of the samples that belong to summary, histogram and counter families
+const PROM_FAMILY_SUFFIXES: &[&str] = &["_bucket", "_sum", "_count", "_total", "_created"];
+
+#[derive(Debug)]
+pub struct Metric {
+    pub name: String,
+    pub value: f64,
+    /// Unix time in seconds carried by the input line
+    pub timestamp: Option<f64>,
+}
+
+/// Turns input lines into metrics according to the source format
+#[derive(Debug, Clone, Default)]
+pub struct LineParser {
+    pub format: InputFormat,
+    /// Non-numeric JSON fields that are appended to the series names as labels
+    pub json_labels: Vec<String>,
+    /// Glob patterns of the Prometheus metric families to keep, all if empty
+    pub prom_families: Vec<String>,
+    /// Field (JSON) or name (kv) that holds the timestamp of the line
+    pub time_field: Option<String>,
+    pub time_unit: TimeUnit,
+    /// Names of the metrics that are passed on, the others are dropped right after parsing
+    pub filter: NameFilter,
+    /// Prepended to the metric names with a `.`, e.g. the alias of the source
+    pub prefix: Option<String>,
+}
+
+impl LineParser {
+    /// Parses a single input line, every item is either a metric or a parsing error
+    pub fn parse_line(&self, line: &str) -> Vec<Result<Metric>> {
+        match self.format {
+            InputFormat::Kv => self.parse_kv_line(line),
+            InputFormat::Json => match self.parse_json(line) {
+                Ok(metrics) => metrics.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            },
+            InputFormat::Prom => match self.parse_prom(line) {
+                Ok(metric) => metric.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            },
+            InputFormat::Influx => match parse_influx(line, self.time_unit) {
+                Ok(metrics) => metrics.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            },
+        }
+    }
+
+    /// Parses `name=value` pairs, the line may start with a timestamp like
+    /// `@1700000000.123 cpu=5;mem=3`
+    fn parse_kv_line(&self, mut line: &str) -> Vec<Result<Metric>> {
+        let mut timestamp = None;
+        if let Some(rest) = line.strip_prefix('@') {
+            let (ts, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            match parse_timestamp(ts, self.time_unit) {
+                Ok(ts) => timestamp = Some(ts),
+                Err(e) => return vec![Err(e)],
+            }
+            line = rest;
+        }
+
+        let mut metrics = Vec::new();
+        for item in line.split(';').filter(|x| !x.is_empty()) {
+            match (item.split_once('='), &self.time_field) {
+                (Some((name, value)), Some(field)) if name == field => {
+                    match parse_timestamp(value, self.time_unit) {
+                        Ok(ts) => timestamp = Some(ts),
+                        Err(e) => metrics.push(Err(e)),
+                    }
+                }
+                _ => metrics.push(parse_kv(item)),
+            }
+        }
+        for metric in metrics.iter_mut().flatten() {
+            metric.timestamp = timestamp;
+        }
+        metrics
+    }
+
+    /// Parses a sample line like `http_requests_total{code="200"} 1027 1395066363000`,
+    /// the label set is kept in the series name
+    fn parse_prom(&self, line: &str) -> Result<Option<Metric>> {
+        let line = line.trim();
+        // Comments, HELP, TYPE and EOF lines carry no samples
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let name_end = line
+            .find(|c: char| c == '{' || c.is_whitespace())
+            .ok_or_else(|| eyre!("missing sample value"))?;
+        let (family, rest) = line.split_at(name_end);
+        let mut rest = rest.trim_start();
+        if !self.keep_prom_family(family) {
+            return Ok(None);
+        }
+
+        let mut name = family.to_string();
+        if rest.starts_with('{') {
+            let (labels, tail) = parse_prom_labels(rest)?;
+            if !labels.is_empty() {
+                name.push('{');
+                name.push_str(&labels.join(","));
+                name.push('}');
+            }
+            rest = tail;
+        }
+
+        // An OpenMetrics exemplar like `# {trace_id="x"} 1 123` follows the sample
+        let sample = rest.split_once(" #").map_or(rest, |(sample, _)| sample);
+        let mut fields = sample.split_whitespace();
+        let value = fields
+            .next()
+            .ok_or_else(|| eyre!("missing sample value"))?
+            .parse::<f64>()?;
+        let timestamp = fields
+            .next()
+            .map(|ts| parse_timestamp(ts, self.time_unit))
+            .transpose()?;
+        Ok(Some(Metric {
+            name,
+            value,
+            timestamp,
+        }))
+    }
+
+    fn keep_prom_family(&self, name: &str) -> bool {
+        if self.prom_families.is_empty() {
+            return true;
+        }
+        let family = PROM_FAMILY_SUFFIXES
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))
+            .unwrap_or(name);
+        self.prom_families
+            .iter()
+            .any(|p| glob_match(p, name) || glob_match(p, family))
+    }
+
+    fn parse_json(&self, line: &str) -> Result<Vec<Metric>> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(Vec::new());
+        }
+        let value: Value = serde_json::from_str(line)?;
+        let Value::Object(_) = value else {
+            bail!("expected a JSON object");
+        };
+
+        let mut labels = Vec::new();
+        for label in &self.json_labels {
+            let label_value = match lookup_json(&value, label) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Bool(b)) => b.to_string(),
+                Some(Value::Number(n)) => n.to_string(),
+                _ => continue,
+            };
+            labels.push(format!("{label}=\"{label_value}\""));
+        }
+        let suffix = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        };
+
+        let time_field = self.time_field.as_deref();
+        let timestamp = match time_field.and_then(|field| lookup_json(&value, field)) {
+            Some(Value::Number(n)) => Some(scale_timestamp(
+                n.as_f64().unwrap_or_default(),
+                self.time_unit,
+            )),
+            Some(Value::String(s)) => Some(parse_timestamp(s, self.time_unit)?),
+            Some(other) => bail!("invalid timestamp {other}"),
+            None => None,
+        };
+
+        let mut metrics = Vec::new();
+        flatten_json(&value, &mut String::new(), &mut |name, value| {
+            if Some(name) == time_field || self.json_labels.iter().any(|l| l == name) {
+                return;
+            }
+            metrics.push(Metric {
+                name: format!("{name}{suffix}"),
+                value,
+                timestamp,
+            });
+        });
+        Ok(metrics)
+    }
+}
+
+/// Parses a `{key="value",...}` label set and returns the labels in the
+/// canonical form along with the rest of the line
+fn parse_prom_labels(input: &str) -> Result<(Vec<String>, &str)> {
+    let mut labels = Vec::new();
+    let mut rest = input[1..].trim_start();
+    loop {
+        if let Some(tail) = rest.strip_prefix('}') {
+            return Ok((labels, tail));
+        }
+
+        let (key, tail) = rest
+            .split_once('=')
+            .ok_or_else(|| eyre!("invalid label set {input:?}"))?;
+        let tail = tail
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or_else(|| eyre!("unquoted label value in {input:?}"))?;
+
+        // Find the closing quote, skipping escaped characters
+        let mut escaped = false;
+        let end = tail
+            .char_indices()
+            .find(|&(_, c)| {
+                let found = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                found
+            })
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| eyre!("unterminated label value in {input:?}"))?;
+        labels.push(format!("{}=\"{}\"", key.trim(), &tail[..end]));
+
+        rest = tail[end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+}
+
+/// Parses a line like `cpu,host=a usage_user=1.5,procs=3i 1700000000000000000`,
+/// the timestamp is in nanoseconds unless the unit is given explicitly
+fn parse_influx(line: &str, unit: TimeUnit) -> Result<Vec<Metric>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(Vec::new());
+    }
+
+    let sections = split_unescaped(line, ' ');
+    let (key, fields, timestamp) = match sections.as_slice() {
+        [key, fields] => (key, fields, None),
+        [key, fields, timestamp] => (key, fields, Some(timestamp)),
+        _ => bail!("expected measurement, field set and optional timestamp"),
+    };
+    // Like the `precision` of the write API, the line protocol defaults to nanoseconds
+    let unit = match unit {
+        TimeUnit::Auto => TimeUnit::Ns,
+        unit => unit,
+    };
+    let timestamp = timestamp
+        .map(|ts| ts.parse::<i64>().map(|ts| scale_timestamp(ts as f64, unit)))
+        .transpose()
+        .wrap_err("invalid timestamp")?;
+
+    let mut key = split_unescaped(key, ',').into_iter();
+    let measurement = unescape(key.next().unwrap_or_default());
+    if measurement.is_empty() {
+        bail!("missing measurement");
+    }
+    let mut tags = Vec::new();
+    for tag in key {
+        let Some((name, value)) = split_once_unescaped(tag, '=') else {
+            bail!("invalid tag {tag:?}");
+        };
+        tags.push(format!("{}=\"{}\"", unescape(name), unescape(value)));
+    }
+    let suffix = if tags.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", tags.join(","))
+    };
+
+    let mut metrics = Vec::new();
+    for field in split_unescaped(fields, ',') {
+        let Some((name, value)) = split_once_unescaped(field, '=') else {
+            bail!("invalid field {field:?}");
+        };
+        let value = match value {
+            // String fields can't be charted
+            v if v.starts_with('"') => continue,
+            "t" | "T" | "true" | "True" | "TRUE" => 1.0,
+            "f" | "F" | "false" | "False" | "FALSE" => 0.0,
+            v => v
+                .strip_suffix(['i', 'u'])
+                .unwrap_or(v)
+                .parse::<f64>()
+                .wrap_err_with(|| format!("invalid field value {v:?}"))?,
+        };
+        metrics.push(Metric {
+            name: format!("{measurement}.{}{suffix}", unescape(name)),
+            value,
+            timestamp,
+        });
+    }
+    Ok(metrics)
+}
+
+/// Splits on the delimiter unless it is escaped with `\` or inside a quoted string
+fn split_unescaped(input: &str, delim: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut escaped, mut quoted) = (0, false, false);
+    for (idx, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == delim && !quoted => {
+                parts.push(&input[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+fn split_once_unescaped(input: &str, delim: char) -> Option<(&str, &str)> {
+    let first = split_unescaped(input, delim).into_iter().next()?;
+    input
+        .get(first.len() + delim.len_utf8()..)
+        .map(|rest| (first, rest))
+}
+
+fn unescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parses Unix time in seconds, milliseconds, microseconds or nanoseconds,
+/// or an RFC3339 date like `2023-11-14T22:13:20.5Z`, into Unix seconds
+pub fn parse_timestamp(value: &str, unit: TimeUnit) -> Result<f64> {
+    match value.parse::<f64>() {
+        // NaN and infinities would break the ordering of the points in time
+        Ok(ts) if !ts.is_finite() => bail!("invalid timestamp {value:?}"),
+        Ok(ts) => Ok(scale_timestamp(ts, unit)),
+        Err(_) => parse_rfc3339(value).ok_or_else(|| eyre!("invalid timestamp {value:?}")),
+    }
+}
+
+fn scale_timestamp(ts: f64, unit: TimeUnit) -> f64 {
+    let unit = match unit {
+        // Seconds below 1e11 cover dates up to year 5138
+        TimeUnit::Auto if ts.abs() < 1e11 => TimeUnit::S,
+        TimeUnit::Auto if ts.abs() < 1e14 => TimeUnit::Ms,
+        TimeUnit::Auto if ts.abs() < 1e17 => TimeUnit::Us,
+        TimeUnit::Auto => TimeUnit::Ns,
+        unit => unit,
+    };
+    match unit {
+        TimeUnit::Ms => ts / 1e3,
+        TimeUnit::Us => ts / 1e6,
+        TimeUnit::Ns => ts / 1e9,
+        _ => ts,
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.frac][Z|±HH:MM]`, a missing offset means UTC
+fn parse_rfc3339(value: &str) -> Option<f64> {
+    let num = |from: usize, to: usize| value.get(from..to)?.parse::<i64>().ok();
+    let sep =
+        |idx: usize, allowed: &[u8]| allowed.contains(value.as_bytes().get(idx)?).then_some(());
+
+    sep(4, b"-")?;
+    sep(7, b"-")?;
+    sep(10, b"Tt ")?;
+    sep(13, b":")?;
+    sep(16, b":")?;
+    let (year, month, day) = (num(0, 4)?, num(5, 7)?, num(8, 10)?);
+    let (hour, minute, second) = (num(11, 13)?, num(14, 16)?, num(17, 19)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    // Seconds up to 60 allow for a leap second
+    if !(1..=12).contains(&month)
+        || !(1..=month_days).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &value[19..];
+    let mut fraction = 0.0;
+    if let Some(tail) = rest.strip_prefix('.') {
+        let digits = tail
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        fraction = format!("0.{}", &tail[..digits]).parse().ok()?;
+        rest = &tail[digits..];
+    }
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = rest[1..].split_once(':')?;
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    // Days since the Unix epoch for the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some(seconds as f64 + fraction)
+}
+
+fn parse_kv(metric: &str) -> Result<Metric> {
+    let Some((name, rest)) = metric.split_once('=') else {
+        bail!("missing delimiter '='");
+    };
+    Ok(Metric {
+        name: name.to_string(),
+        value: rest.parse::<f64>()?,
+        timestamp: None,
+    })
+}
+
+/// Finds a value by its dotted path, e.g. `host.name`
+fn lookup_json<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Calls `emit` for every numeric leaf, nested names are joined with dots
+fn flatten_json(value: &Value, prefix: &mut String, emit: &mut impl FnMut(&str, f64)) {
+    let mut descend = |key: &str, value: &Value, prefix: &mut String| {
+        let len = prefix.len();
+        if !prefix.is_empty() {
+            prefix.push('.');
+        }
+        prefix.push_str(key);
+        flatten_json(value, prefix, emit);
+        prefix.truncate(len);
+    };
+
+    match value {
+        Value::Number(n) => {
+            if let Some(v) = n.as_f64() {
+                emit(prefix, v)
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                descend(key, value, prefix);
+            }
+        }
+        Value::Array(items) => {
+            for (idx, value) in items.iter().enumerate() {
+                descend(&idx.to_string(), value, prefix);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prom(line: &str) -> Metric {
+        let parser = LineParser {
+            format: InputFormat::Prom,
+            ..LineParser::default()
+        };
+        parser.parse_prom(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn numeric_timestamps() {
+        assert_eq!(
+            parse_timestamp("1700000000.5", TimeUnit::Auto).unwrap(),
+            1700000000.5
+        );
+        assert_eq!(
+            parse_timestamp("1700000000000", TimeUnit::Auto).unwrap(),
+            1700000000.0
+        );
+        assert_eq!(
+            parse_timestamp("1700000000", TimeUnit::Ms).unwrap(),
+            1700000.0
+        );
+        for value in ["NaN", "nan", "inf", "-inf", "infinity", "1e400"] {
+            assert!(parse_timestamp(value, TimeUnit::Auto).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn rfc3339_offsets_and_fractions() {
+        let ts = |value| parse_timestamp(value, TimeUnit::Auto).unwrap();
+        assert_eq!(ts("1970-01-01T00:00:00Z"), 0.0);
+        assert_eq!(ts("2023-11-14T22:13:20Z"), 1700000000.0);
+        assert_eq!(ts("2023-11-14 22:13:20"), 1700000000.0);
+        assert_eq!(ts("2023-11-14t22:13:20z"), 1700000000.0);
+        assert_eq!(ts("2023-11-14T22:13:20.5Z"), 1700000000.5);
+        assert_eq!(ts("2023-11-14T22:13:20.125+00:00"), 1700000000.125);
+        assert_eq!(ts("2023-11-15T00:13:20+02:00"), 1700000000.0);
+        assert_eq!(ts("2023-11-14T17:13:20-05:00"), 1700000000.0);
+        assert_eq!(ts("2023-11-15T03:43:20.25+05:30"), 1700000000.25);
+    }
+
+    #[test]
+    fn rfc3339_leap_years() {
+        let ts = |value| parse_timestamp(value, TimeUnit::Auto).unwrap();
+        assert_eq!(ts("2024-02-29T00:00:00Z"), 1709164800.0);
+        assert_eq!(ts("2024-03-01T00:00:00Z"), 1709251200.0);
+        assert_eq!(ts("2000-02-29T12:00:00Z"), 951825600.0);
+        assert!(parse_timestamp("2023-02-29T00:00:00Z", TimeUnit::Auto).is_err());
+        assert!(parse_timestamp("1900-02-29T00:00:00Z", TimeUnit::Auto).is_err());
+    }
+
+    #[test]
+    fn rfc3339_before_epoch() {
+        let ts = |value| parse_timestamp(value, TimeUnit::Auto).unwrap();
+        assert_eq!(ts("1969-12-31T23:59:59Z"), -1.0);
+        assert_eq!(ts("1969-12-31T23:59:59.5Z"), -0.5);
+        assert_eq!(ts("1960-06-15T08:30:00Z"), -301246200.0);
+        assert_eq!(ts("1900-03-01T00:00:00Z"), -2203891200.0);
+        assert_eq!(ts("1601-01-01T00:00:00Z"), -11644473600.0);
+    }
+
+    #[test]
+    fn rfc3339_invalid() {
+        for value in [
+            "2023-13-01T00:00:00Z",
+            "2023-04-31T00:00:00Z",
+            "2023-11-14T24:00:00Z",
+            "2023-11-14T22:60:00Z",
+            "2023-11-14T22:13:20+0200",
+            "2023-11-14T22:13:20 UTC",
+            "2023-11-14",
+        ] {
+            assert!(parse_timestamp(value, TimeUnit::Auto).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn influx_timestamp_unit() {
+        let metrics = parse_influx(
+            "cpu,host=a usage=1.5,procs=3i 1700000000000000000",
+            TimeUnit::Auto,
+        )
+        .unwrap();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].name, r#"cpu.usage{host="a"}"#);
+        assert_eq!(metrics[1].value, 3.0);
+        assert_eq!(metrics[0].timestamp, Some(1700000000.0));
+
+        // Small values are still nanoseconds unless the unit says otherwise
+        let metrics = parse_influx("cpu usage=1 5000000000", TimeUnit::Auto).unwrap();
+        assert_eq!(metrics[0].timestamp, Some(5.0));
+        let metrics = parse_influx("cpu usage=1 1700000000", TimeUnit::S).unwrap();
+        assert_eq!(metrics[0].timestamp, Some(1700000000.0));
+        let metrics = parse_influx("cpu usage=1 1700000000000", TimeUnit::Ms).unwrap();
+        assert_eq!(metrics[0].timestamp, Some(1700000000.0));
+    }
+
+    #[test]
+    fn prom_labels_keep_escapes() {
+        let (labels, rest) = parse_prom_labels(r#"{path="C:\dir",msg="say \"hi\""} 1"#).unwrap();
+        assert_eq!(labels, [r#"path="C:\dir""#, r#"msg="say \"hi\"""#]);
+        assert_eq!(rest, " 1");
+    }
+
+    #[test]
+    fn prom_labels_with_braces_and_commas_in_values() {
+        let (labels, rest) = parse_prom_labels(r#"{a="}",b="x,y={z}"} 2"#).unwrap();
+        assert_eq!(labels, [r#"a="}""#, r#"b="x,y={z}""#]);
+        assert_eq!(rest, " 2");
+    }
+
+    #[test]
+    fn prom_labels_trailing_comma_and_spaces() {
+        let (labels, rest) = parse_prom_labels(r#"{ a = "1" , b="2", } 3"#).unwrap();
+        assert_eq!(labels, [r#"a="1""#, r#"b="2""#]);
+        assert_eq!(rest, " 3");
+
+        let (labels, _) = parse_prom_labels("{} 4").unwrap();
+        assert!(labels.is_empty());
+    }
+
+    #[test]
+    fn prom_labels_errors() {
+        assert!(parse_prom_labels(r#"{a="1} 2"#).is_err());
+        assert!(parse_prom_labels("{a=1} 2").is_err());
+        assert!(parse_prom_labels("{a} 2").is_err());
+    }
+
+    #[test]
+    fn prom_sample_with_exemplar() {
+        let metric = prom(r#"foo_bucket{le="1"} 5 # {trace_id="x"} 1 123"#);
+        assert_eq!(metric.name, r#"foo_bucket{le="1"}"#);
+        assert_eq!(metric.value, 5.0);
+        assert_eq!(metric.timestamp, None);
+
+        let metric = prom(r#"foo_bucket{le="+Inf"} 7 1700000000 # {trace_id="x"} 1"#);
+        assert_eq!(metric.value, 7.0);
+        assert_eq!(metric.timestamp, Some(1700000000.0));
+    }
+
+    #[test]
+    fn prom_hash_inside_label_value() {
+        let metric = prom(r#"foo{a=" # "} 1"#);
+        assert_eq!(metric.name, r#"foo{a=" # "}"#);
+        assert_eq!(metric.value, 1.0);
+    }
+}
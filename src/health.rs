@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// How many of the last stderr lines are kept per source
+const STDERR_LINES: usize = 100;
+/// How many of the last restarts are marked on the chart per source
+const RESTART_MARKS: usize = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SourceState {
+    Starting,
+    Running,
+    Restarting,
+    Failed,
+    Finished,
+}
+
+impl Display for SourceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceState::Starting => f.write_str("starting"),
+            SourceState::Running => f.write_str("running"),
+            SourceState::Restarting => f.write_str("restarting"),
+            SourceState::Failed => f.write_str("failed"),
+            SourceState::Finished => f.write_str("finished"),
+        }
+    }
+}
+
+/// What is known about a source for the health panel
+#[derive(Debug, Clone)]
+pub struct SourceHealth {
+    pub name: String,
+    /// `-p`, `-c`, `stdin`, `file`, `statsd` or `replay`
+    pub kind: &'static str,
+    pub state: SourceState,
+    /// Exit status of the last run or the reason it failed
+    pub exit_status: Option<String>,
+    pub restarts: u64,
+    /// When the last restarts happened
+    pub restarted_at: VecDeque<Instant>,
+    pub stderr: VecDeque<String>,
+    pub last_metric: Option<Instant>,
+    /// Lines that had no metric to parse
+    pub unparsed: u64,
+}
+
+/// Health of the sources shared by their threads and the UI
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    sources: Arc<Mutex<Vec<SourceHealth>>>,
+}
+
+impl Health {
+    pub fn register(&self, name: &str, kind: &'static str) -> Reporter {
+        let mut sources = self.sources.lock().unwrap();
+        sources.push(SourceHealth {
+            name: name.to_string(),
+            kind,
+            state: SourceState::Starting,
+            exit_status: None,
+            restarts: 0,
+            restarted_at: VecDeque::new(),
+            stderr: VecDeque::new(),
+            last_metric: None,
+            unparsed: 0,
+        });
+        Reporter {
+            health: self.clone(),
+            idx: sources.len() - 1,
+        }
+    }
+
+    pub fn sources(&self) -> Vec<SourceHealth> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    pub fn name(&self, source: usize) -> String {
+        self.sources.lock().unwrap()[source].name.clone()
+    }
+
+    /// Names of the sources by their index
+    pub fn names(&self) -> Vec<String> {
+        let sources = self.sources.lock().unwrap();
+        sources.iter().map(|source| source.name.clone()).collect()
+    }
+
+    /// Indexes of the restarted sources with the times of their restarts
+    pub fn restarts(&self) -> Vec<(usize, Vec<Instant>)> {
+        self.sources
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| !source.restarted_at.is_empty())
+            .map(|(idx, source)| (idx, source.restarted_at.iter().copied().collect()))
+            .collect()
+    }
+}
+
+/// Updates the health of a single source
+#[derive(Debug, Clone)]
+pub struct Reporter {
+    health: Health,
+    idx: usize,
+}
+
+impl Reporter {
+    /// Index of the source in the order of registration, it tells the
+    /// signals of the sources apart even when their commands are the same
+    pub fn source(&self) -> usize {
+        self.idx
+    }
+
+    fn update(&self, change: impl FnOnce(&mut SourceHealth)) {
+        change(&mut self.health.sources.lock().unwrap()[self.idx]);
+    }
+
+    pub fn set_state(&self, state: SourceState) {
+        self.update(|source| source.state = state);
+    }
+
+    /// The source stopped, `state` tells what happens next
+    pub fn exited(&self, state: SourceState, status: impl Display) {
+        self.update(|source| {
+            source.state = state;
+            source.exit_status = Some(status.to_string());
+        });
+    }
+
+    /// The source is being started again
+    pub fn restarted(&self) {
+        self.update(|source| {
+            source.state = SourceState::Restarting;
+            source.restarts += 1;
+            if source.restarted_at.len() == RESTART_MARKS {
+                source.restarted_at.pop_front();
+            }
+            source.restarted_at.push_back(Instant::now());
+        });
+    }
+
+    pub fn stderr(&self, line: &str) {
+        self.update(|source| {
+            if source.stderr.len() == STDERR_LINES {
+                source.stderr.pop_front();
+            }
+            source.stderr.push_back(line.to_string());
+        });
+    }
+
+    /// Accounts a line with the numbers of its metrics and parsing errors
+    pub fn line(&self, metrics: usize, errors: usize) {
+        if metrics == 0 && errors == 0 {
+            return;
+        }
+        self.update(|source| {
+            if metrics > 0 {
+                source.last_metric = Some(Instant::now());
+            } else {
+                source.unparsed += 1;
+            }
+        });
+    }
+}
@@ -1,29 +1,113 @@
 mod app;
+mod children;
+mod config;
+mod duration;
+mod export;
+mod expr;
+mod format;
+mod health;
+mod panel;
+mod pattern;
+mod record;
+mod replay;
+mod source;
+mod statsd;
 mod term;
+mod transform;
 mod ui;
 
-use std::time::Instant;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use clap::Parser;
-use color_eyre::Result;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use color_eyre::{eyre::eyre, Result};
+use regex::Regex;
 
-use crate::app::App;
+use crate::app::{App, ChartScale};
+use crate::config::Profile;
+use crate::export::{ExportFormat, ExportRange};
+use crate::expr::Derived;
+use crate::format::{InputFormat, LineParser, TimeUnit};
+use crate::health::Health;
+use crate::panel::{Layout, Panel};
+use crate::pattern::NameFilter;
+use crate::record::Recorder;
+use crate::replay::ReplayClock;
+use crate::source::{CommandDefaults, ExecMode, Overlap, RestartPolicy, SourceSpec};
+use crate::transform::{Rule, Transform};
 
 #[derive(Parser)]
 #[command(name = "tlook")]
 #[command(about = "A terminal-based metrics visualizer")]
 pub struct Args {
-    /// Long-running processes to monitor (can be specified multiple times)
+    /// Config file with the profiles [default: ~/.config/tlook/config.toml]
+    #[arg(long = "config", value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Load the named profile of the config file, the command line options override it
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Long-running processes to monitor (can be specified multiple times).
+    /// Per-source options can be prefixed, e.g. "format=json:./app"
     #[arg(short = 'p', long = "process", action = clap::ArgAction::Append)]
-    pub processes: Vec<String>,
+    pub processes: Vec<SourceSpec>,
 
-    /// Short-lived commands to run repeatedly (can be specified multiple times)
+    /// Short-lived commands to run repeatedly (can be specified multiple times).
+    /// Per-source options can be prefixed, e.g. "format=json:./app" or "5s,timeout=2s:df -h /"
     #[arg(short = 'c', long = "command", action = clap::ArgAction::Append)]
-    pub commands: Vec<String>,
+    pub commands: Vec<SourceSpec>,
+
+    /// Interval for repeating commands, in seconds or e.g. 500ms or 5m (default: 1)
+    #[arg(long = "interval", value_name = "DURATION", default_value = "1", value_parser = duration::parse_interval)]
+    pub interval: Duration,
+
+    /// Kill the commands running longer than that and leave a gap in their series
+    #[arg(long = "timeout", value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// What happens to the runs of a command that are due while it is still running
+    #[arg(long = "overlap", value_enum, default_value_t = Overlap::Skip)]
+    pub overlap: Overlap,
+
+    /// Parse the stderr of the commands and processes for metrics too, it is only kept otherwise
+    #[arg(long = "parse-stderr")]
+    pub parse_stderr: bool,
+
+    /// Add series about the sources: <source>.duration_s, .exit_code and .lines of every
+    /// command run and <source>.restarts of the processes
+    #[arg(long = "source-metrics")]
+    pub source_metrics: bool,
+
+    /// Delay before restarting an exited process, it doubles while the process keeps exiting early
+    #[arg(long = "restart-delay", value_name = "DURATION", default_value = "1s", value_parser = duration::parse_duration)]
+    pub restart_delay: Duration,
 
-    /// Interval in seconds for repeating commands (default: 1)
-    #[arg(long = "interval", default_value = "1")]
-    pub interval: u64,
+    /// The longest delay before restarting a process
+    #[arg(long = "restart-max-delay", value_name = "DURATION", default_value = "1m", value_parser = duration::parse_duration)]
+    pub restart_max_delay: Duration,
+
+    /// Give up on a process after restarting it that many times (default: never)
+    #[arg(long = "max-restarts", value_name = "N")]
+    pub max_restarts: Option<u64>,
+
+    /// Run the processes once without restarting them
+    #[arg(long = "once", conflicts_with = "max_restarts")]
+    pub once: bool,
+
+    /// Whether the commands run in the shell: when they look like scripts, always or never
+    #[arg(long = "exec-mode", value_enum, default_value_t = ExecMode::Auto)]
+    pub exec_mode: ExecMode,
+
+    /// The shell running the scripts with its options, e.g. "bash -o pipefail"
+    #[arg(long = "shell", default_value = "sh")]
+    pub shell: String,
+
+    /// Prefix the series of every source with its name, e.g. ping.time, not only of the aliased ones
+    #[arg(long = "prefix-sources")]
+    pub prefix_sources: bool,
 
     /// Read from stdin instead of commands/processes
     #[arg(long = "stdin")]
@@ -32,33 +116,462 @@ pub struct Args {
     /// Read from a file instead of commands/processes
     #[arg(short = 'f', long = "file")]
     pub file: Option<String>,
+
+    /// Replay a timestamped recording on its original schedule
+    #[arg(long = "replay", value_name = "FILE")]
+    pub replay: Option<String>,
+
+    /// Record every received point to the file, it can be opened later with --replay
+    #[arg(long = "record", value_name = "FILE")]
+    pub record: Option<String>,
+
+    /// Chart the per-second rate of the counters matching the glob pattern (can be specified multiple times)
+    #[arg(long = "rate", value_name = "GLOB", action = clap::ArgAction::Append)]
+    pub rate: Vec<String>,
+
+    /// Smooth the matching series with a moving average over N points or a number of seconds, e.g. "ping*:10" or "ping*:30s"
+    #[arg(long = "sma", value_name = "GLOB:N|SECONDSs", value_parser = transform::parse_sma, action = clap::ArgAction::Append)]
+    pub sma: Vec<Rule>,
+
+    /// Smooth the matching series with an exponentially weighted moving average, e.g. "ping*:0.2"
+    #[arg(long = "ewma", value_name = "GLOB:ALPHA", value_parser = transform::parse_ewma, action = clap::ArgAction::Append)]
+    pub ewma: Vec<Rule>,
+
+    /// Smooth the matching series with a rolling median over N points, e.g. "ping*:5"
+    #[arg(long = "median", value_name = "GLOB:N", value_parser = transform::parse_median, action = clap::ArgAction::Append)]
+    pub median: Vec<Rule>,
+
+    /// Show the raw line dimmed behind the smoothed one
+    #[arg(long = "show-raw")]
+    pub show_raw: bool,
+
+    /// Keep only the series with names matching the regex (can be specified multiple times)
+    #[arg(long = "include", value_name = "REGEX", action = clap::ArgAction::Append)]
+    pub include: Vec<Regex>,
+
+    /// Drop the series with names matching the regex (can be specified multiple times)
+    #[arg(long = "exclude", value_name = "REGEX", action = clap::ArgAction::Append)]
+    pub exclude: Vec<Regex>,
+
+    /// Derived series, e.g. "used_pct = used / total * 100" (can be specified multiple times)
+    #[arg(long = "expr", value_name = "NAME = EXPR", action = clap::ArgAction::Append)]
+    pub exprs: Vec<Derived>,
+
+    /// Align the series of --expr by time buckets of the width, e.g. 10s, instead of the nearest points
+    #[arg(long = "expr-bucket", value_name = "DURATION", value_parser = duration::parse_interval)]
+    pub expr_bucket: Option<Duration>,
+
+    /// A chart panel with the series matching any of the comma-separated name patterns
+    /// or source indexes, e.g. "latency*,#2" for the second -p/-c source counting -p first
+    /// (can be specified multiple times, the other series get a panel of their own)
+    #[arg(long = "panel", value_name = "GLOB|#N,...", action = clap::ArgAction::Append)]
+    pub panels: Vec<Panel>,
+
+    /// How the chart panels are arranged
+    #[arg(long = "layout", value_enum, default_value_t = Layout::Stacked)]
+    pub layout: Layout,
+
+    /// Width of the chart window, e.g. 90s, 5m or 1h [default: 60s]
+    #[arg(long = "window", value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub window: Option<Duration>,
+
+    /// How much history is kept, e.g. 30m or 2h [default: 1h]
+    #[arg(long = "history", value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub history: Option<Duration>,
+
+    /// How often the chart is redrawn, e.g. 100ms [default: 250ms]
+    #[arg(long = "tick", value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub tick: Option<Duration>,
+
+    /// Scale of the y axis [default: linear]
+    #[arg(long = "scale", value_enum)]
+    pub scale: Option<ChartScale>,
+
+    /// Show the axis labels
+    #[arg(long = "axis-labels")]
+    pub axis_labels: bool,
+
+    /// Hide the legend
+    #[arg(long = "no-legend", overrides_with = "legend")]
+    pub no_legend: bool,
+
+    /// Show the legend, e.g. when the config profile hides it
+    #[arg(long = "legend", overrides_with = "no_legend")]
+    pub legend: bool,
+
+    /// Show the cursor
+    #[arg(long = "cursor")]
+    pub cursor: bool,
+
+    /// Group the legend entries by their source
+    #[arg(long = "group-legend")]
+    pub group_legend: bool,
+
+    /// Export the collected points to the file on exit
+    #[arg(long = "export", value_name = "FILE")]
+    pub export: Option<String>,
+
+    /// Format of the exported points, also used by the export key bindings
+    #[arg(long = "export-format", value_enum, default_value_t = ExportFormat::Csv)]
+    pub export_format: ExportFormat,
+
+    /// Points exported on exit
+    #[arg(long = "export-range", value_enum, default_value_t = ExportRange::All)]
+    pub export_range: ExportRange,
+
+    /// Replay speed factor, e.g. 10x
+    #[arg(long = "speed", default_value = "1x", value_parser = replay::parse_speed)]
+    pub speed: f64,
+
+    /// Listen for StatsD datagrams on the UDP address
+    #[arg(long = "statsd", value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:8125")]
+    pub statsd: Option<String>,
+
+    /// Interval the StatsD aggregates are flushed at, e.g. 500ms or 10s
+    #[arg(long = "statsd-flush", value_name = "DURATION", default_value = "1s", value_parser = duration::parse_duration)]
+    pub statsd_flush: Duration,
+
+    /// Default format of the input lines
+    #[arg(long = "format", value_enum, default_value_t = InputFormat::Kv)]
+    pub format: InputFormat,
+
+    /// JSON field to use as a series label instead of ignoring it (can be specified multiple times)
+    #[arg(long = "json-label", action = clap::ArgAction::Append)]
+    pub json_labels: Vec<String>,
+
+    /// Prometheus metric family to keep, glob patterns are supported (can be specified multiple times)
+    #[arg(long = "prom-family", action = clap::ArgAction::Append)]
+    pub prom_families: Vec<String>,
+
+    /// Field (json) or name (kv) holding the timestamp of the line
+    #[arg(long = "time-field")]
+    pub time_field: Option<String>,
+
+    /// Unit of the numeric timestamps in the input, auto means nanoseconds for influx
+    #[arg(long = "time-unit", value_enum, default_value_t = TimeUnit::Auto)]
+    pub time_unit: TimeUnit,
+}
+
+impl Args {
+    /// Takes the options missing from the command line from the config profile
+    fn merge(&mut self, matches: &ArgMatches, profile: &mut Profile) {
+        merge(
+            matches,
+            "processes",
+            &mut self.processes,
+            profile.processes.take(),
+        );
+        merge(
+            matches,
+            "commands",
+            &mut self.commands,
+            profile.commands.take(),
+        );
+        merge(
+            matches,
+            "interval",
+            &mut self.interval,
+            profile.interval.take(),
+        );
+        merge(
+            matches,
+            "timeout",
+            &mut self.timeout,
+            profile.timeout.take().map(Some),
+        );
+        merge(
+            matches,
+            "overlap",
+            &mut self.overlap,
+            profile.overlap.take(),
+        );
+        merge(
+            matches,
+            "parse_stderr",
+            &mut self.parse_stderr,
+            profile.parse_stderr.take(),
+        );
+        merge(
+            matches,
+            "source_metrics",
+            &mut self.source_metrics,
+            profile.source_metrics.take(),
+        );
+        merge(
+            matches,
+            "restart_delay",
+            &mut self.restart_delay,
+            profile.restart_delay.take(),
+        );
+        merge(
+            matches,
+            "restart_max_delay",
+            &mut self.restart_max_delay,
+            profile.restart_max_delay.take(),
+        );
+        merge(
+            matches,
+            "max_restarts",
+            &mut self.max_restarts,
+            profile.max_restarts.take().map(Some),
+        );
+        merge(matches, "once", &mut self.once, profile.once.take());
+        merge(
+            matches,
+            "exec_mode",
+            &mut self.exec_mode,
+            profile.exec_mode.take(),
+        );
+        merge(matches, "shell", &mut self.shell, profile.shell.take());
+        merge(
+            matches,
+            "prefix_sources",
+            &mut self.prefix_sources,
+            profile.prefix_sources.take(),
+        );
+        merge(matches, "format", &mut self.format, profile.format.take());
+        merge(matches, "rate", &mut self.rate, profile.rate.take());
+        merge(matches, "sma", &mut self.sma, profile.sma.take());
+        merge(matches, "ewma", &mut self.ewma, profile.ewma.take());
+        merge(matches, "median", &mut self.median, profile.median.take());
+        merge(
+            matches,
+            "show_raw",
+            &mut self.show_raw,
+            profile.show_raw.take(),
+        );
+        merge(matches, "exprs", &mut self.exprs, profile.expr.take());
+        merge(
+            matches,
+            "include",
+            &mut self.include,
+            profile.include.take(),
+        );
+        merge(
+            matches,
+            "exclude",
+            &mut self.exclude,
+            profile.exclude.take(),
+        );
+        merge(matches, "panels", &mut self.panels, profile.panels.take());
+        merge(matches, "layout", &mut self.layout, profile.layout.take());
+    }
+}
+
+/// Replaces the argument with the config value unless it was given on the command line
+fn merge<T>(matches: &ArgMatches, id: &str, arg: &mut T, value: Option<T>) {
+    if matches.value_source(id) == Some(ValueSource::CommandLine) {
+        return;
+    }
+    if let Some(value) = value {
+        *arg = value;
+    }
 }
 
 fn main() -> Result<()> {
     env_logger::init();
     term::install_hooks()?;
+    children::stop_on_signals()?;
 
-    let args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
+    let mut profile = config::load(args.config.as_deref(), args.profile.as_deref())?;
+    args.merge(&matches, &mut profile);
+    source::name_sources(args.processes.iter_mut().chain(args.commands.iter_mut()))
+        .map_err(|e| eyre!(e))?;
     let now = Instant::now();
+    let health = Health::default();
 
-    let input = if args.stdin {
-        app::get_input_channel_from_stdin(now)?
+    let recorder = match &args.record {
+        Some(path) => {
+            let command = shell_words::join(std::env::args());
+            let sources: Vec<String> = args
+                .processes
+                .iter()
+                .map(|p| format!("-p {}", p.command))
+                .chain(args.commands.iter().map(|c| format!("-c {}", c.command)))
+                .collect();
+            Some(Recorder::create(path, now, &command, &sources, &health)?)
+        }
+        None => None,
+    };
+    let parser = LineParser {
+        format: args.format,
+        json_labels: args.json_labels,
+        prom_families: args.prom_families,
+        time_field: args.time_field,
+        time_unit: args.time_unit,
+        filter: NameFilter {
+            include: args.include,
+            exclude: args.exclude,
+        },
+        prefix: None,
+    };
+
+    let mut replay_clock = None;
+    let input = if let Some(file) = args.replay {
+        let clock = Arc::new(ReplayClock::new(args.speed));
+        replay_clock = Some(clock.clone());
+        replay::get_input_channel_from_replay(file, parser, clock, &health)?
+    } else if args.stdin {
+        app::get_input_channel_from_stdin(parser, now, &health)?
     } else if let Some(file) = args.file {
-        app::get_input_channel_from_file(file, now)?
+        app::get_input_channel_from_file(file, parser, now, &health)?
+    } else if let Some(addr) = args.statsd {
+        statsd::get_input_channel_from_statsd(addr, args.statsd_flush, parser.filter, now, &health)?
     } else if !args.processes.is_empty() || !args.commands.is_empty() {
         app::get_input_channel_from_processes_and_commands(
             args.processes,
             args.commands,
-            args.interval,
+            CommandDefaults {
+                interval: args.interval,
+                timeout: args.timeout,
+                overlap: args.overlap,
+                parse_stderr: args.parse_stderr,
+                source_metrics: args.source_metrics,
+                prefix_sources: args.prefix_sources,
+                restart: RestartPolicy {
+                    delay: args.restart_delay,
+                    max_delay: args.restart_max_delay.max(args.restart_delay),
+                    max_restarts: if args.once {
+                        Some(0)
+                    } else {
+                        args.max_restarts
+                    },
+                },
+                mode: args.exec_mode,
+                shell: args.shell,
+            },
+            parser,
             now,
+            &health,
         )?
     } else {
-        eprintln!("Error: Must specify either --stdin, --file, or one or more -p/-c commands");
+        eprintln!(
+            "Error: Must specify either --replay, --stdin, --file, --statsd, or one or more -p/-c commands"
+        );
         std::process::exit(1);
     };
 
-    let mut terminal = term::init()?;
-    let result = App::new(input, now).run(&mut terminal);
-    term::restore().expect("terminal restore");
+    let mut terminal = match term::init() {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            children::shutdown();
+            return Err(e.into());
+        }
+    };
+    let mut app = App::new(input, now);
+    if let Some(window) = args.window.or(profile.window) {
+        app.set_window(window);
+    }
+    if let Some(history) = args.history.or(profile.history) {
+        app.history = history;
+    }
+    if let Some(tick_rate) = args.tick.or(profile.tick_rate) {
+        app.tick_rate = tick_rate;
+    }
+    if let Some(scale) = args.scale.or(profile.scale) {
+        app.scale_mode = scale;
+    }
+    if args.legend || args.no_legend {
+        app.legend = args.legend;
+    } else if let Some(legend) = profile.legend {
+        app.legend = legend;
+    }
+    if args.axis_labels {
+        app.axis_labels = true;
+    } else if let Some(axis_labels) = profile.axis_labels {
+        app.axis_labels = axis_labels;
+    }
+    if args.cursor {
+        app.show_cursor = true;
+    } else if let Some(cursor) = profile.cursor {
+        app.show_cursor = cursor;
+    }
+    if args.group_legend {
+        app.group_legend = true;
+    } else if let Some(group_legend) = profile.group_legend {
+        app.group_legend = group_legend;
+    }
+    app.colors = profile.colors.unwrap_or_default();
+    app.export_format = args.export_format;
+    app.exprs = args.exprs;
+    app.expr_bucket = args.expr_bucket.map(|bucket| bucket.as_secs_f64());
+    for pattern in args.rate {
+        app.transforms.add(pattern, Transform::Rate);
+    }
+    app.transforms.extend(args.sma);
+    app.transforms.extend(args.ewma);
+    app.transforms.extend(args.median);
+    app.show_raw = args.show_raw;
+    app.panels = args.panels;
+    app.layout = args.layout;
+    app = app.with_health(health);
+    if let Some(clock) = replay_clock {
+        app = app.with_replay(clock);
+    }
+    if let Some(recorder) = recorder {
+        app = app.with_recorder(recorder);
+    }
+    let result = app.run(&mut terminal);
+    if let Err(e) = term::restore() {
+        // The children are still stopped when the terminal is gone
+        log::warn!("failed to restore the terminal: {e}");
+    }
+    children::shutdown();
+    if let Some(path) = args.export {
+        app.export(&path, args.export_format, args.export_range)?;
+    }
+    if let Some(sig) = children::interrupted() {
+        std::process::exit(128 + sig);
+    }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The arguments with the options missing from the command line taken from the profile
+    fn merged(cli: &[&str], mut profile: Profile) -> Args {
+        let matches =
+            Args::command().get_matches_from(std::iter::once("tlook").chain(cli.iter().copied()));
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.merge(&matches, &mut profile);
+        args
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            interval: Some(Duration::from_secs(10)),
+            overlap: Some(Overlap::Queue),
+            commands: Some(vec!["df -h /".parse().unwrap()]),
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn command_line_overrides_the_profile() {
+        let args = merged(&["--interval", "5s", "-c", "uptime"], profile());
+        assert_eq!(args.interval, Duration::from_secs(5));
+        assert_eq!(args.commands[0].command, "uptime");
+        // The options missing from the command line come from the profile
+        assert_eq!(args.overlap, Overlap::Queue);
+    }
+
+    #[test]
+    fn profile_overrides_the_defaults() {
+        let args = merged(&[], profile());
+        assert_eq!(args.interval, Duration::from_secs(10));
+        assert_eq!(args.commands[0].command, "df -h /");
+        assert_eq!(args.exec_mode, ExecMode::Auto);
+    }
+
+    #[test]
+    fn last_legend_flag_wins() {
+        let args = merged(&["--no-legend", "--legend"], Profile::default());
+        assert!(args.legend && !args.no_legend);
+        let args = merged(&["--legend", "--no-legend"], Profile::default());
+        assert!(!args.legend && args.no_legend);
+    }
+}
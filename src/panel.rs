@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::pattern::glob_match;
+
+/// How the chart panels are arranged
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    /// One panel above another
+    #[default]
+    Stacked,
+    /// Rows and columns of panels
+    Grid,
+}
+
+impl Layout {
+    /// Number of `(rows, columns)` to place the panels in
+    pub fn grid(&self, panels: usize) -> (usize, usize) {
+        match self {
+            Layout::Stacked => (panels, 1),
+            Layout::Grid => {
+                let columns = (panels as f64).sqrt().ceil().max(1.0) as usize;
+                (panels.div_ceil(columns), columns)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    /// Series name glob pattern
+    Name(String),
+    /// `#N`, the series of the N-th `-p`/`-c` source
+    Source(usize),
+}
+
+/// A chart panel showing the series matching any of its selectors, e.g. `latency*,#2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panel {
+    pub title: String,
+    selectors: Vec<Selector>,
+}
+
+impl Panel {
+    /// Whether the series belongs to the panel, `source` is the 1-based index of its source
+    pub fn matches(&self, name: &str, source: Option<usize>) -> bool {
+        self.selectors.iter().any(|selector| match selector {
+            Selector::Name(pattern) => glob_match(pattern, name),
+            Selector::Source(idx) => source == Some(*idx),
+        })
+    }
+}
+
+impl FromStr for Panel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let selectors = s
+            .split(',')
+            .map(str::trim)
+            .filter(|selector| !selector.is_empty())
+            .map(|selector| match selector.strip_prefix('#') {
+                Some(idx) => match idx.parse() {
+                    Ok(idx) if idx > 0 => Ok(Selector::Source(idx)),
+                    _ => Err(format!(
+                        "invalid source index {selector:?}, expected #1, #2, ..."
+                    )),
+                },
+                None => Ok(Selector::Name(selector.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if selectors.is_empty() {
+            return Err("a panel needs at least one series pattern or source index".to_string());
+        }
+        Ok(Self {
+            title: s.trim().to_string(),
+            selectors,
+        })
+    }
+}
@@ -0,0 +1,49 @@
+use regex::Regex;
+
+/// Matches `text` against a shell-like glob pattern where `*` stands for
+/// any sequence of characters and `?` for any single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern and the text position it matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Series names to keep, `--include` and `--exclude` regular expressions
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    /// Any of them has to match, all names are kept if empty
+    pub include: Vec<Regex>,
+    /// None of them may match
+    pub exclude: Vec<Regex>,
+}
+
+impl NameFilter {
+    pub fn keeps(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(name)))
+            && !self.exclude.iter().any(|re| re.is_match(name))
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::Instant,
+};
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+use crate::app::{self, Signal};
+use crate::health::Health;
+
+/// The first line of a recording, the rest of the file is described by [`Entry`]
+pub const HEADER: &str = "# tlook recording v1";
+
+/// A line of a recording
+#[derive(Debug, PartialEq)]
+pub enum Entry {
+    /// `# start <unix time>`, the wall-clock time the session started at
+    Start(f64),
+    /// `# command <args>`, the command line of the recorded session
+    Command(String),
+    /// `# source <source>`, one of the sources of the recorded session
+    Source(String),
+    /// `# from <name>`, the name of the source of the points that follow
+    From(String),
+    /// `@<unix time> <name>=<value>`
+    Point {
+        timestamp: f64,
+        name: String,
+        value: f64,
+    },
+    /// Any other comment
+    Comment,
+}
+
+/// Parses a recording line, a name may contain `=` since a point holds a single metric
+pub fn parse_entry(line: &str) -> Result<Entry> {
+    if let Some(comment) = line.strip_prefix('#') {
+        let (key, value) = comment
+            .trim()
+            .split_once(' ')
+            .unwrap_or((comment.trim(), ""));
+        return Ok(match key {
+            "start" => Entry::Start(value.parse()?),
+            "command" => Entry::Command(value.to_string()),
+            "source" => Entry::Source(value.to_string()),
+            "from" => Entry::From(value.to_string()),
+            _ => Entry::Comment,
+        });
+    }
+
+    let Some(point) = line.strip_prefix('@') else {
+        bail!("missing timestamp");
+    };
+    let (timestamp, metric) = point
+        .split_once(' ')
+        .ok_or_else(|| eyre!("missing metric"))?;
+    let (name, value) = metric
+        .rsplit_once('=')
+        .ok_or_else(|| eyre!("missing delimiter '='"))?;
+    Ok(Entry::Point {
+        timestamp: timestamp.parse()?,
+        name: name.to_string(),
+        value: value.parse()?,
+    })
+}
+
+/// Appends every received signal to a recording that can be opened with `--replay`
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: f64,
+    /// Names the sources in the `# from` lines
+    health: Health,
+    last_source: Option<usize>,
+}
+
+impl Recorder {
+    pub fn create(
+        path: &str,
+        start_time: Instant,
+        command: &str,
+        sources: &[String],
+        health: &Health,
+    ) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let start = app::unix_time(start_time);
+        writeln!(out, "{HEADER}")?;
+        writeln!(out, "# start {start:.6}")?;
+        writeln!(out, "# command {command}")?;
+        for source in sources {
+            writeln!(out, "# source {source}")?;
+        }
+        out.flush()?;
+
+        Ok(Self {
+            out,
+            start,
+            health: health.clone(),
+            last_source: None,
+        })
+    }
+
+    pub fn write(&mut self, signal: &Signal) -> io::Result<()> {
+        if self.last_source != Some(signal.source) {
+            writeln!(self.out, "# from {}", self.health.name(signal.source))?;
+            self.last_source = Some(signal.source);
+        }
+        writeln!(
+            self.out,
+            "@{:.6} {}={}",
+            self.start + signal.x_time,
+            signal.name,
+            signal.value
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::app::Signal;
+use crate::format::{LineParser, Metric};
+use crate::health::{Health, Reporter, SourceState};
+use crate::record::{self, Entry};
+
+/// How often the replay reader re-checks the clock while waiting for the next point
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct ClockState {
+    /// Recording time at the anchor
+    position: f64,
+    anchor: Instant,
+    speed: f64,
+    paused: bool,
+    /// Unix time of the recording start once it is known
+    origin: Option<f64>,
+}
+
+/// Recording time shared by the replay reader and the UI, it can be paused,
+/// sped up and moved around
+pub struct ReplayClock {
+    state: Mutex<ClockState>,
+}
+
+impl ReplayClock {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            state: Mutex::new(ClockState {
+                position: 0.0,
+                anchor: Instant::now(),
+                speed,
+                paused: false,
+                origin: None,
+            }),
+        }
+    }
+
+    /// Seconds since the start of the recording
+    pub fn now(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        Self::position(&state)
+    }
+
+    pub fn origin(&self) -> Option<f64> {
+        self.state.lock().unwrap().origin
+    }
+
+    fn set_origin(&self, origin: f64) {
+        self.state.lock().unwrap().origin = Some(origin);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state.lock().unwrap().speed
+    }
+
+    fn position(state: &ClockState) -> f64 {
+        if state.paused {
+            state.position
+        } else {
+            state.position + state.anchor.elapsed().as_secs_f64() * state.speed
+        }
+    }
+
+    /// Applies the change to the clock re-anchored at the current instant
+    fn update(&self, change: impl FnOnce(&mut ClockState)) {
+        let mut state = self.state.lock().unwrap();
+        state.position = Self::position(&state);
+        state.anchor = Instant::now();
+        change(&mut state);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.update(|state| state.paused = paused);
+    }
+
+    pub fn set_speed(&self, speed: f64) {
+        self.update(|state| state.speed = speed);
+    }
+
+    pub fn seek(&self, offset: f64) {
+        self.update(|state| state.position = (state.position + offset).max(0.0));
+    }
+}
+
+/// The replayed sources in the health panel, they are registered as the
+/// recording names them
+struct ReplaySources {
+    health: Health,
+    reporters: Vec<(String, Reporter)>,
+}
+
+impl ReplaySources {
+    fn reporter(&mut self, source: &str) -> Reporter {
+        if let Some((_, reporter)) = self.reporters.iter().find(|(name, _)| name == source) {
+            return reporter.clone();
+        }
+        let reporter = self.health.register(source, "replay");
+        reporter.set_state(SourceState::Running);
+        self.reporters.push((source.to_string(), reporter.clone()));
+        reporter
+    }
+
+    fn set_state(&self, state: SourceState) {
+        for (_, reporter) in &self.reporters {
+            reporter.set_state(state);
+        }
+    }
+}
+
+/// Parses a replay speed factor like `10x`, `0.5x` or `2`
+pub fn parse_speed(value: &str) -> Result<f64, String> {
+    let speed = value
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(value)
+        .parse::<f64>()
+        .map_err(|e| format!("invalid speed {value:?}: {e}"))?;
+    if !speed.is_finite() || speed <= 0.0 {
+        return Err(format!("speed must be positive, got {value:?}"));
+    }
+    Ok(speed)
+}
+
+/// Reads the timestamped recording and emits its points when the replay clock
+/// reaches them. The replay starts at the session start of a `--record`
+/// recording or at the first timestamp of any other file, lines without a
+/// timestamp reuse the previous one.
+pub fn get_input_channel_from_replay(
+    file: String,
+    parser: LineParser,
+    clock: Arc<ReplayClock>,
+    health: &Health,
+) -> io::Result<Receiver<Signal>> {
+    let mut lines = BufReader::new(File::open(&file)?).lines().peekable();
+    let recording = matches!(lines.peek(), Some(Ok(header)) if header == record::HEADER);
+    let (tx, rx) = mpsc::channel();
+    let mut sources = ReplaySources {
+        health: health.clone(),
+        reporters: Vec::new(),
+    };
+    if !recording {
+        sources.reporter(&file);
+    }
+
+    thread::spawn(move || {
+        let mut origin = None;
+        let mut source = file.clone();
+        let mut x_time = 0.0;
+        for line in lines {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    log::error!("Failed to read the recording '{file}': {e}");
+                    sources
+                        .reporter(&source)
+                        .exited(SourceState::Failed, format!("read error: {e}"));
+                    return;
+                }
+            };
+
+            let metrics = if recording {
+                match record::parse_entry(&line) {
+                    Ok(Entry::Start(start)) => {
+                        origin = Some(start);
+                        clock.set_origin(start);
+                        continue;
+                    }
+                    Ok(Entry::From(from)) => {
+                        source = from;
+                        continue;
+                    }
+                    Ok(Entry::Point {
+                        timestamp,
+                        name,
+                        value,
+                    }) => vec![Ok(Metric {
+                        name,
+                        value,
+                        timestamp: Some(timestamp),
+                    })],
+                    Ok(_) => continue,
+                    Err(e) => vec![Err(e)],
+                }
+            } else {
+                parser.parse_line(&line)
+            };
+
+            let reporter = sources.reporter(&source);
+            let (mut sent, mut errors) = (0, 0);
+            for metric in metrics {
+                let metric = match metric {
+                    Ok(metric) if !parser.filter.keeps(&metric.name) => continue,
+                    Ok(metric) => metric,
+                    Err(e) => {
+                        log::debug!("ignore parsing err {e} for {line} from '{file}'");
+                        errors += 1;
+                        continue;
+                    }
+                };
+                if let Some(timestamp) = metric.timestamp {
+                    let origin = *origin.get_or_insert_with(|| {
+                        clock.set_origin(timestamp);
+                        timestamp
+                    });
+                    x_time = timestamp - origin;
+                }
+
+                // Wait in short steps so that pausing, seeking and speed changes apply promptly
+                loop {
+                    let ahead = x_time - clock.now();
+                    if ahead <= 0.0 {
+                        break;
+                    }
+                    // Far-future points overflow a Duration, they just wait in full steps
+                    let wait = Duration::try_from_secs_f64(ahead / clock.speed())
+                        .map_or(POLL_INTERVAL, |wait| wait.min(POLL_INTERVAL));
+                    thread::sleep(wait);
+                }
+
+                let res = tx.send(Signal {
+                    name: metric.name,
+                    x_time,
+                    value: metric.value,
+                    source: reporter.source(),
+                });
+                if res.is_err() {
+                    log::error!("receiver closed? {res:?}");
+                    return;
+                }
+                sent += 1;
+            }
+            reporter.line(sent, errors);
+        }
+        sources.set_state(SourceState::Finished);
+        log::info!("Replay of '{file}' finished");
+    });
+    Ok(rx)
+}
@@ -0,0 +1,586 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::duration::{parse_duration, parse_interval};
+use crate::format::InputFormat;
+
+/// What happens to the runs of a `-c` command that are due while it is still running
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Overlap {
+    /// The missed runs are skipped, the next one starts on schedule
+    #[default]
+    Skip,
+    /// The latest missed run starts as soon as the command finishes
+    Queue,
+}
+
+impl Overlap {
+    /// Index of the run after `slot`, the runs are due at multiples of the
+    /// interval since the start
+    pub fn next_slot(&self, slot: u64, interval: Duration, elapsed: Duration) -> u64 {
+        if interval.is_zero() {
+            return slot.saturating_add(1);
+        }
+        let due = elapsed.as_secs_f64() / interval.as_secs_f64();
+        let next = match self {
+            Overlap::Skip => due.ceil(),
+            Overlap::Queue => due.floor(),
+        };
+        slot.saturating_add(1).max(next as u64)
+    }
+}
+
+/// How the command of a source is started
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ExecMode {
+    /// In the shell when the command has pipes, redirects, `;`, `&&`, `||` or `$`, directly otherwise
+    #[default]
+    Auto,
+    /// Always in the shell
+    Shell,
+    /// Directly, the command is split into the program and its arguments like a shell would do
+    Exec,
+}
+
+/// When a `-p` process is restarted after it exits
+#[derive(Debug, Copy, Clone)]
+pub struct RestartPolicy {
+    /// Delay before the first restart, it doubles with every restart that follows a short run
+    pub delay: Duration,
+    pub max_delay: Duration,
+    /// Restarts before giving up, `Some(0)` runs the process once
+    pub max_restarts: Option<u64>,
+}
+
+impl RestartPolicy {
+    /// Delay before the restart that follows `attempt` short runs in a row,
+    /// up to half of it is taken off at random to spread the restarts
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        delay.mul_f64(rand::random_range(0.5..=1.0))
+    }
+
+    /// A run at least as long as the longest delay resets the backoff
+    pub fn is_short_run(&self, ran_for: Duration) -> bool {
+        ran_for < self.max_delay
+    }
+
+    /// Whether the process is left exited after `restarts` restarts
+    pub fn gives_up(&self, restarts: u64) -> bool {
+        self.max_restarts.is_some_and(|max| restarts >= max)
+    }
+}
+
+/// Settings of the `-p`/`-c` sources that don't have their own
+#[derive(Debug, Clone)]
+pub struct CommandDefaults {
+    pub interval: Duration,
+    pub timeout: Option<Duration>,
+    pub overlap: Overlap,
+    pub parse_stderr: bool,
+    /// Whether the sources report their runs as `<source>.<metric>` series
+    pub source_metrics: bool,
+    /// Whether the series of every source are prefixed with its name, not only of the aliased ones
+    pub prefix_sources: bool,
+    pub restart: RestartPolicy,
+    pub mode: ExecMode,
+    /// The shell with its options, the command is passed to it after `-c`
+    pub shell: String,
+}
+
+/// A `-p`/`-c` source with its per-source options.
+///
+/// Options are given as a comma separated prefix terminated by `:`,
+/// e.g. `format=json:./my-app --verbose` or `interval=5s,timeout=2s,overlap=queue:df -h /`
+/// or `stderr=parse,max_restarts=3:ping host` or `mode=exec,cwd=/srv,env=KUBECONFIG=/tmp/kc:kubectl top pods`,
+/// a bare duration is the interval, e.g. `5s:df -h /`, and a bare word is the alias
+/// prefixing the series of the source, e.g. `ping:ping host` gives `ping.time`. Values
+/// with `:`, `,` or spaces are double-quoted, e.g. `shell="bash -o pipefail":cmd`.
+/// A prefix with an option key or a duration has to be valid, a string without them is taken
+/// as a plain command unless its prefix is made of durations and aliases only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpec {
+    pub command: String,
+    /// Name given to the source, its series are prefixed with it
+    pub alias: Option<String>,
+    /// Name of the source in the legend and the series about the source itself, see [`name_sources`]
+    pub name: Option<String>,
+    pub format: Option<InputFormat>,
+    /// How often a `-c` command is repeated instead of `--interval`
+    pub interval: Option<Duration>,
+    /// How long a `-c` command may run instead of `--timeout`
+    pub timeout: Option<Duration>,
+    /// What happens to the overlapping runs of a `-c` command instead of `--overlap`
+    pub overlap: Option<Overlap>,
+    /// Whether the stderr lines are parsed for metrics instead of `--parse-stderr`
+    pub parse_stderr: Option<bool>,
+    /// How many times a `-p` process is restarted instead of `--max-restarts`
+    pub max_restarts: Option<u64>,
+    /// Whether the command runs in the shell instead of `--exec-mode`
+    pub mode: Option<ExecMode>,
+    /// The shell instead of `--shell`
+    pub shell: Option<String>,
+    /// Working directory of the command
+    pub cwd: Option<PathBuf>,
+    /// Variables added to the environment of the command
+    pub env: Vec<(String, String)>,
+}
+
+impl SourceSpec {
+    fn plain(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            alias: None,
+            name: None,
+            format: None,
+            interval: None,
+            timeout: None,
+            overlap: None,
+            parse_stderr: None,
+            max_restarts: None,
+            mode: None,
+            shell: None,
+            cwd: None,
+            env: Vec::new(),
+        }
+    }
+
+    /// Name of the program the command runs, e.g. `curl` for `/usr/bin/curl -s host | jq .x`
+    fn program(&self) -> &str {
+        let word = self.command.split_whitespace().next().unwrap_or("source");
+        word.rsplit('/').next().unwrap_or(word)
+    }
+
+    fn parse_options(prefix: &str, command: &str) -> Result<Self, String> {
+        let mut spec = Self::plain(command);
+        for option in split_unquoted(prefix, |c| c == ',') {
+            let invalid = |reason: &str| format!("invalid source option {option:?}: {reason}");
+            if has_unquoted_whitespace(option) {
+                return Err(invalid("quote the values with spaces"));
+            }
+            let Some((key, value)) = option_key(option) else {
+                let option = unquote(option).map_err(|e| invalid(&e))?;
+                match parse_duration(&option) {
+                    Ok(_) => {
+                        spec.interval = Some(parse_interval(&option).map_err(|e| invalid(&e))?)
+                    }
+                    Err(_) if is_alias(&option) => spec.alias = Some(option),
+                    Err(_) => return Err(invalid("expected a duration or an alias")),
+                }
+                continue;
+            };
+            let value = unquote(value).map_err(|e| invalid(&e))?;
+            match key {
+                "name" if is_alias(&value) => spec.alias = Some(value),
+                "name" => return Err(invalid("expected a word like `api-1` or `db.main`")),
+                "format" => {
+                    spec.format =
+                        Some(InputFormat::from_str(&value, true).map_err(|e| invalid(&e))?)
+                }
+                "interval" => {
+                    spec.interval = Some(parse_interval(&value).map_err(|e| invalid(&e))?)
+                }
+                "timeout" => spec.timeout = Some(parse_duration(&value).map_err(|e| invalid(&e))?),
+                "overlap" => {
+                    spec.overlap = Some(Overlap::from_str(&value, true).map_err(|e| invalid(&e))?)
+                }
+                "max_restarts" => {
+                    spec.max_restarts = Some(value.parse().map_err(|e| invalid(&format!("{e}")))?)
+                }
+                "mode" => {
+                    spec.mode = Some(ExecMode::from_str(&value, true).map_err(|e| invalid(&e))?)
+                }
+                "shell" => spec.shell = Some(value),
+                "cwd" => spec.cwd = Some(PathBuf::from(value)),
+                "env" => {
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| invalid("expected env=NAME=VALUE"))?;
+                    spec.env.push((name.to_string(), value.to_string()));
+                }
+                "stderr" => {
+                    spec.parse_stderr = Some(match value.as_str() {
+                        "parse" => true,
+                        "keep" => false,
+                        _ => return Err(invalid("expected parse or keep")),
+                    })
+                }
+                _ => return Err(invalid("unknown option")),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// Splits on the delimiters outside the double quotes, a `\` in the quotes
+/// escapes the next character
+fn split_unquoted(input: &str, is_delim: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (idx, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if is_delim(c) && !quoted => {
+                parts.push(&input[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+fn has_unquoted_whitespace(input: &str) -> bool {
+    split_unquoted(input, char::is_whitespace).len() > 1
+}
+
+/// Drops the double quotes and the escaping `\` in them, e.g. `KUBECONFIG="/a:/b"`
+/// gives `KUBECONFIG=/a:/b`
+fn unquote(value: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(value.len());
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    Ok(out)
+}
+
+const OPTION_KEYS: &[&str] = &[
+    "name",
+    "format",
+    "interval",
+    "timeout",
+    "overlap",
+    "max_restarts",
+    "mode",
+    "shell",
+    "cwd",
+    "env",
+    "stderr",
+];
+
+/// Splits a `key=value` option, the key is a lowercase word like `max_restarts`
+fn option_key(option: &str) -> Option<(&str, &str)> {
+    let (key, value) = option.split_once('=')?;
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+    is_key.then_some((key, value))
+}
+
+/// An alias is a word like `ping`, `api-1` or `db.main`
+fn is_alias(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_alphabetic())
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+impl FromStr for SourceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The prefix ends at the first `:` outside the quotes
+        let prefix = split_unquoted(s, |c| c == ':')[0];
+        let command = s.get(prefix.len() + 1..).map(str::trim_start);
+        let options = split_unquoted(prefix, |c| c == ',');
+        let spaced = has_unquoted_whitespace(prefix);
+        let keys: Vec<&str> = options
+            .iter()
+            .filter_map(|option| option_key(option).map(|(key, _)| key))
+            .collect();
+        // Known options or a duration have to be valid, a prefix of other words may as
+        // well be a part of the command, e.g. `./tool:arg` or `http_proxy=http://proxy:3128 curl`
+        let strict = keys.iter().any(|key| OPTION_KEYS.contains(key))
+            || (!spaced && options.iter().any(|option| parse_duration(option).is_ok()));
+        let spec = match command {
+            Some(command) if strict => Self::parse_options(prefix, command)?,
+            None if strict && unquote(prefix).is_err() => {
+                return Err(format!("unterminated quote in the options of {s:?}"));
+            }
+            Some(command) if !prefix.is_empty() && !spaced => {
+                Self::parse_options(prefix, command).unwrap_or_else(|_| Self::plain(s))
+            }
+            _ => Self::plain(s),
+        };
+
+        if spec.command.trim().is_empty() {
+            return Err("empty command".to_string());
+        }
+        Ok(spec)
+    }
+}
+
+/// Names the sources by their alias or after their program, the sources sharing
+/// a program or clashing with an alias get their 1-based index appended, e.g.
+/// `curl_1` and `curl_3`. The aliases have to be unique.
+pub fn name_sources<'a>(specs: impl IntoIterator<Item = &'a mut SourceSpec>) -> Result<(), String> {
+    let mut specs: Vec<&mut SourceSpec> = specs.into_iter().collect();
+    let mut taken = HashSet::new();
+    for alias in specs.iter().filter_map(|spec| spec.alias.as_ref()) {
+        if !taken.insert(alias.clone()) {
+            return Err(format!("duplicate source name {alias:?}"));
+        }
+    }
+    let mut programs: HashMap<String, usize> = HashMap::new();
+    for spec in specs.iter().filter(|spec| spec.alias.is_none()) {
+        *programs.entry(spec.program().to_string()).or_default() += 1;
+    }
+    for (idx, spec) in specs.iter_mut().enumerate() {
+        if let Some(alias) = &spec.alias {
+            spec.name = Some(alias.clone());
+            continue;
+        }
+        let program = spec.program().to_string();
+        let mut name = match programs[&program] {
+            1 if !taken.contains(&program) => program,
+            _ => format!("{program}_{}", idx + 1),
+        };
+        while taken.contains(&name) {
+            name = format!("{name}_{}", idx + 1);
+        }
+        taken.insert(name.clone());
+        spec.name = Some(name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(value: &str) -> SourceSpec {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn prefix_options() {
+        let parsed = spec("5s,timeout=2s,overlap=queue,stderr=parse:df -h /");
+        assert_eq!(parsed.command, "df -h /");
+        assert_eq!(parsed.interval, Some(Duration::from_secs(5)));
+        assert_eq!(parsed.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(parsed.overlap, Some(Overlap::Queue));
+        assert_eq!(parsed.parse_stderr, Some(true));
+
+        let parsed = spec("ping:ping host");
+        assert_eq!(parsed.alias.as_deref(), Some("ping"));
+        assert_eq!(parsed.command, "ping host");
+    }
+
+    #[test]
+    fn quoted_values_may_hold_colons_commas_and_spaces() {
+        let parsed = spec(r#"env=KUBECONFIG="/a:/b",cwd="/srv/my app":kubectl top pods"#);
+        assert_eq!(parsed.env, [("KUBECONFIG".into(), "/a:/b".into())]);
+        assert_eq!(parsed.cwd, Some(PathBuf::from("/srv/my app")));
+        assert_eq!(parsed.command, "kubectl top pods");
+
+        let parsed = spec(r#"env="PATH=/bin:/usr/bin",shell="bash -o pipefail":a | b"#);
+        assert_eq!(parsed.env, [("PATH".into(), "/bin:/usr/bin".into())]);
+        assert_eq!(parsed.shell.as_deref(), Some("bash -o pipefail"));
+        assert_eq!(parsed.command, "a | b");
+
+        let parsed = spec(r#"shell="sh -c \"x\" \\":cmd"#);
+        assert_eq!(parsed.shell.as_deref(), Some(r#"sh -c "x" \"#));
+    }
+
+    #[test]
+    fn invalid_options_are_errors() {
+        for value in [
+            "shell=bash -o pipefail:cmd",
+            "5s,shell=bash -o pipefail:cmd",
+            "interval=abc:cmd",
+            "5s,foo=1:cmd",
+            "format=yaml:cmd",
+            "stderr=drop:cmd",
+            "env=KUBECONFIG:cmd",
+            "name=two words:cmd",
+            r#"shell="bash:cmd"#,
+            "5s:",
+            "0s:cmd",
+            "0:cmd",
+            "ping,0ms:cmd",
+            "interval=0s:cmd",
+        ] {
+            assert!(value.parse::<SourceSpec>().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn commands_without_options() {
+        for value in [
+            "curl -s http://localhost:8080/stats",
+            "./tool.sh:arg",
+            "FOO=1:cmd",
+            "x=1; echo a:b",
+            "echo a=b:c",
+            "foo=1:cmd",
+            "http_proxy=http://proxy:3128 curl -s localhost:9100/metrics",
+            "no_proxy=localhost:8080 curl x",
+        ] {
+            assert_eq!(spec(value), SourceSpec::plain(value), "{value}");
+        }
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn on_time_runs_take_the_next_slot() {
+        for overlap in [Overlap::Skip, Overlap::Queue] {
+            assert_eq!(overlap.next_slot(0, SECOND, Duration::from_millis(300)), 1);
+            assert_eq!(overlap.next_slot(4, SECOND, Duration::from_millis(4999)), 5);
+            assert_eq!(overlap.next_slot(4, SECOND, Duration::from_secs(5)), 5);
+        }
+    }
+
+    #[test]
+    fn overrunning_runs_skip_or_queue_the_missed_slots() {
+        // Slot 1 is due at 1s while slot 0 is still running
+        assert_eq!(
+            Overlap::Skip.next_slot(0, SECOND, Duration::from_millis(1200)),
+            2
+        );
+        assert_eq!(
+            Overlap::Queue.next_slot(0, SECOND, Duration::from_millis(1200)),
+            1
+        );
+        // Slots 1 to 3 are missed
+        assert_eq!(
+            Overlap::Skip.next_slot(0, SECOND, Duration::from_millis(3500)),
+            4
+        );
+        assert_eq!(
+            Overlap::Queue.next_slot(0, SECOND, Duration::from_millis(3500)),
+            3
+        );
+    }
+
+    /// Start times in milliseconds of the runs taking the durations, like the command loop
+    fn starts(overlap: Overlap, interval: Duration, runs: &[u64]) -> Vec<u128> {
+        let mut starts = Vec::new();
+        let (mut slot, mut finished) = (0, Duration::ZERO);
+        for &run in runs {
+            let start = interval.mul_f64(slot as f64).max(finished);
+            starts.push(start.as_millis());
+            finished = start + Duration::from_millis(run);
+            slot = overlap.next_slot(slot, interval, finished);
+        }
+        starts
+    }
+
+    #[test]
+    fn slots_stay_aligned_to_the_start() {
+        let runs = [200, 2500, 200, 200, 1300, 100];
+        assert_eq!(
+            starts(Overlap::Skip, SECOND, &runs),
+            [0, 1000, 4000, 5000, 6000, 8000]
+        );
+        // A queued run starts late, the following ones are back on the cadence
+        assert_eq!(
+            starts(Overlap::Queue, SECOND, &runs),
+            [0, 1000, 3500, 4000, 5000, 6300]
+        );
+    }
+
+    fn policy(max_restarts: Option<u64>) -> RestartPolicy {
+        RestartPolicy {
+            delay: SECOND,
+            max_delay: Duration::from_secs(10),
+            max_restarts,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_longest_delay() {
+        let policy = policy(None);
+        for (attempt, full) in [(0, 1), (1, 2), (2, 4), (3, 8), (4, 10), (5, 10), (100, 10)] {
+            let full = Duration::from_secs(full);
+            for _ in 0..100 {
+                let delay = policy.backoff(attempt);
+                // The jitter takes up to half of the delay off
+                assert!(delay >= full / 2 && delay <= full, "{attempt}: {delay:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn long_runs_reset_the_backoff() {
+        let policy = policy(None);
+        assert!(policy.is_short_run(Duration::from_secs(9)));
+        assert!(!policy.is_short_run(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn gives_up_after_max_restarts() {
+        assert!(!policy(None).gives_up(u64::MAX));
+        // Run once
+        assert!(policy(Some(0)).gives_up(0));
+        let policy = policy(Some(3));
+        assert!(!policy.gives_up(2));
+        assert!(policy.gives_up(3));
+    }
+
+    fn names(specs: &[&str]) -> Result<Vec<String>, String> {
+        let mut specs: Vec<SourceSpec> = specs.iter().map(|s| s.parse().unwrap()).collect();
+        name_sources(specs.iter_mut())?;
+        Ok(specs.into_iter().map(|spec| spec.name.unwrap()).collect())
+    }
+
+    #[test]
+    fn names_after_program_or_alias() {
+        assert_eq!(
+            names(&[
+                "ping host",
+                "db:psql -c 'select 1'",
+                "/usr/bin/curl -s host | jq .x"
+            ]),
+            Ok(vec!["ping".into(), "db".into(), "curl".into()])
+        );
+    }
+
+    #[test]
+    fn shared_programs_get_their_index() {
+        assert_eq!(
+            names(&["curl -s a", "ping host", "curl -s b"]),
+            Ok(vec!["curl_1".into(), "ping".into(), "curl_3".into()])
+        );
+    }
+
+    #[test]
+    fn program_names_give_way_to_aliases() {
+        assert_eq!(
+            names(&["curl:ping host", "curl -s a"]),
+            Ok(vec!["curl".into(), "curl_2".into()])
+        );
+        assert_eq!(
+            names(&["curl_2:ping host", "curl -s a", "curl -s b"]),
+            Ok(vec!["curl_2".into(), "curl_2_2".into(), "curl_3".into()])
+        );
+    }
+
+    #[test]
+    fn duplicate_aliases_are_rejected() {
+        assert_eq!(
+            names(&["a:ping host", "a:ping host"]),
+            Err("duplicate source name \"a\"".into())
+        );
+        assert!(names(&["name=a:ping host", "a:ping other"]).is_err());
+    }
+}
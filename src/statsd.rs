@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{self, ErrorKind},
+    net::UdpSocket,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::bail, Result};
+
+use crate::app::Signal;
+use crate::health::{Health, SourceState};
+use crate::pattern::NameFilter;
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+#[derive(Debug, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+    /// `ms`, `h` and `d` are aggregated the same way
+    Timer,
+    Set,
+}
+
+#[derive(Debug, PartialEq)]
+struct Sample<'a> {
+    name: &'a str,
+    value: &'a str,
+    kind: Kind,
+    sample_rate: f64,
+}
+
+/// Parses a single StatsD metric line, e.g. `api.requests:1|c|@0.1`
+fn parse_sample(line: &str) -> Result<Sample<'_>> {
+    let Some((name, rest)) = line.split_once(':') else {
+        bail!("missing delimiter ':'");
+    };
+    let mut fields = rest.split('|');
+    let value = fields.next().unwrap_or_default();
+    let kind = match fields.next() {
+        Some("c") => Kind::Counter,
+        Some("g") => Kind::Gauge,
+        Some("ms" | "h" | "d") => Kind::Timer,
+        Some("s") => Kind::Set,
+        Some(kind) => bail!("unsupported metric type {kind:?}"),
+        None => bail!("missing metric type"),
+    };
+
+    let mut sample_rate = 1.0;
+    for field in fields {
+        // Tags (`#tag:value`) and other extensions are ignored
+        if let Some(rate) = field.strip_prefix('@') {
+            sample_rate = rate.parse::<f64>()?;
+            if sample_rate <= 0.0 || sample_rate > 1.0 {
+                bail!("invalid sample rate {sample_rate}");
+            }
+        }
+    }
+    if name.is_empty() || value.is_empty() {
+        bail!("empty name or value");
+    }
+    Ok(Sample {
+        name,
+        value,
+        kind,
+        sample_rate,
+    })
+}
+
+#[derive(Default)]
+struct Timer {
+    count: f64,
+    sum: f64,
+    max: f64,
+}
+
+/// Aggregates the samples received during a flush interval
+#[derive(Default)]
+struct Aggregator {
+    counters: BTreeMap<String, f64>,
+    gauges: BTreeMap<String, f64>,
+    timers: BTreeMap<String, Timer>,
+    sets: BTreeMap<String, HashSet<String>>,
+}
+
+impl Aggregator {
+    fn add(&mut self, sample: Sample) -> Result<()> {
+        if sample.kind == Kind::Set {
+            self.sets
+                .entry(sample.name.to_string())
+                .or_default()
+                .insert(sample.value.to_string());
+            return Ok(());
+        }
+
+        let value = sample.value.parse::<f64>()?;
+        match sample.kind {
+            Kind::Counter => {
+                *self.counters.entry(sample.name.to_string()).or_default() +=
+                    value / sample.sample_rate;
+            }
+            Kind::Gauge => {
+                let gauge = self.gauges.entry(sample.name.to_string()).or_default();
+                // A signed value modifies the current gauge instead of replacing it
+                if sample.value.starts_with(['+', '-']) {
+                    *gauge += value;
+                } else {
+                    *gauge = value;
+                }
+            }
+            Kind::Timer => {
+                let timer = self.timers.entry(sample.name.to_string()).or_default();
+                timer.max = if timer.count == 0.0 {
+                    value
+                } else {
+                    timer.max.max(value)
+                };
+                timer.count += 1.0 / sample.sample_rate;
+                timer.sum += value / sample.sample_rate;
+            }
+            Kind::Set => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Turns the interval aggregates into signals. Counters become per-second
+    /// rates and keep reporting zero once seen, gauges keep their latest value.
+    fn flush(&mut self, interval: f64, x_time: f64, source: usize) -> Vec<Signal> {
+        let mut signals = Vec::new();
+        let mut push = |name: String, value: f64| {
+            signals.push(Signal {
+                name,
+                x_time,
+                value,
+                source,
+            })
+        };
+
+        for (name, count) in self.counters.iter_mut() {
+            push(name.clone(), *count / interval);
+            *count = 0.0;
+        }
+        for (name, value) in self.gauges.iter() {
+            push(name.clone(), *value);
+        }
+        for (name, timer) in std::mem::take(&mut self.timers) {
+            push(format!("{name}.mean"), timer.sum / timer.count);
+            push(format!("{name}.max"), timer.max);
+            push(format!("{name}.rate"), timer.count / interval);
+        }
+        for (name, set) in std::mem::take(&mut self.sets) {
+            push(name, set.len() as f64);
+        }
+        signals
+    }
+}
+
+/// Listens for StatsD datagrams on the UDP address and emits the aggregates
+/// kept by the filter every `flush` interval
+pub fn get_input_channel_from_statsd(
+    addr: String,
+    flush: Duration,
+    filter: NameFilter,
+    start_time: Instant,
+    health: &Health,
+) -> io::Result<Receiver<Signal>> {
+    if flush.is_zero() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "statsd flush interval must be positive",
+        ));
+    }
+    let socket = UdpSocket::bind(&addr)?;
+    log::info!("Listening for StatsD on {}", socket.local_addr()?);
+    let reporter = health.register(&addr, "statsd");
+    reporter.set_state(SourceState::Running);
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut aggregator = Aggregator::default();
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        let mut next_flush = Instant::now() + flush;
+
+        loop {
+            let timeout = next_flush.saturating_duration_since(Instant::now());
+            if let Err(e) = socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1)))) {
+                log::error!("Failed to set StatsD socket timeout: {e}");
+                reporter.exited(SourceState::Failed, e);
+                return;
+            }
+
+            match socket.recv_from(&mut buf) {
+                Ok((len, peer)) => {
+                    let packet = String::from_utf8_lossy(&buf[..len]);
+                    for line in packet.lines().filter(|l| !l.trim().is_empty()) {
+                        match parse_sample(line.trim()).and_then(|s| aggregator.add(s)) {
+                            Ok(()) => reporter.line(1, 0),
+                            Err(e) => {
+                                log::debug!("ignore StatsD parsing err {e} for {line} from {peer}");
+                                reporter.line(0, 1);
+                            }
+                        }
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => log::error!("Failed to receive StatsD datagram: {e}"),
+            }
+
+            if Instant::now() >= next_flush {
+                let x_time = start_time.elapsed().as_secs_f64();
+                let signals = aggregator.flush(flush.as_secs_f64(), x_time, reporter.source());
+                for signal in signals.into_iter().filter(|s| filter.keeps(&s.name)) {
+                    if let Err(e) = tx.send(signal) {
+                        log::error!("receiver closed? {e:?}");
+                        return;
+                    }
+                }
+                next_flush += flush;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregate(lines: &[&str]) -> Aggregator {
+        let mut aggregator = Aggregator::default();
+        for line in lines {
+            aggregator.add(parse_sample(line).unwrap()).unwrap();
+        }
+        aggregator
+    }
+
+    fn flush(aggregator: &mut Aggregator, interval: f64) -> Vec<(String, f64)> {
+        aggregator
+            .flush(interval, 1.0, 0)
+            .into_iter()
+            .map(|signal| (signal.name, signal.value))
+            .collect()
+    }
+
+    fn points(points: &[(&str, f64)]) -> Vec<(String, f64)> {
+        points
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn parses_the_metric_types() {
+        let sample = parse_sample("api.requests:1|c").unwrap();
+        assert_eq!(
+            sample,
+            Sample {
+                name: "api.requests",
+                value: "1",
+                kind: Kind::Counter,
+                sample_rate: 1.0,
+            }
+        );
+        assert_eq!(parse_sample("temp:-3|g").unwrap().kind, Kind::Gauge);
+        assert_eq!(parse_sample("db.query:12|ms").unwrap().kind, Kind::Timer);
+        assert_eq!(parse_sample("size:3|h").unwrap().kind, Kind::Timer);
+        assert_eq!(parse_sample("users:bob|s").unwrap().kind, Kind::Set);
+    }
+
+    #[test]
+    fn parses_the_sample_rate_and_ignores_tags() {
+        let sample = parse_sample("hits:1|c|@0.1|#env:prod").unwrap();
+        assert_eq!(sample.sample_rate, 0.1);
+        assert_eq!(parse_sample("hits:1|c|#env:prod").unwrap().sample_rate, 1.0);
+    }
+
+    #[test]
+    fn malformed_datagrams_are_errors() {
+        for line in [
+            "hits",
+            "hits:1",
+            "hits:1|x",
+            ":1|c",
+            "hits:|c",
+            "hits:1|c|@0",
+            "hits:1|c|@1.5",
+            "hits:1|c|@abc",
+        ] {
+            assert!(parse_sample(line).is_err(), "{line}");
+        }
+        let mut aggregator = Aggregator::default();
+        assert!(aggregator.add(parse_sample("hits:abc|c").unwrap()).is_err());
+    }
+
+    #[test]
+    fn counters_become_rates_over_the_flush_interval() {
+        let mut aggregator = aggregate(&["hits:3|c", "hits:2|c", "hits:1|c|@0.5"]);
+        assert_eq!(flush(&mut aggregator, 2.0), points(&[("hits", 3.5)]));
+        // A seen counter keeps reporting zero
+        assert_eq!(flush(&mut aggregator, 2.0), points(&[("hits", 0.0)]));
+    }
+
+    #[test]
+    fn gauges_keep_their_value_and_apply_deltas() {
+        let mut aggregator = aggregate(&["temp:10|g", "temp:+5|g", "temp:-3|g"]);
+        assert_eq!(flush(&mut aggregator, 1.0), points(&[("temp", 12.0)]));
+        aggregator.add(parse_sample("temp:4|g").unwrap()).unwrap();
+        assert_eq!(flush(&mut aggregator, 1.0), points(&[("temp", 4.0)]));
+        assert_eq!(flush(&mut aggregator, 1.0), points(&[("temp", 4.0)]));
+    }
+
+    #[test]
+    fn timers_report_mean_max_and_rate() {
+        let mut aggregator = aggregate(&["db:10|ms", "db:30|ms", "db:20|h|@0.5"]);
+        assert_eq!(
+            flush(&mut aggregator, 2.0),
+            points(&[("db.mean", 20.0), ("db.max", 30.0), ("db.rate", 2.0)])
+        );
+        assert!(flush(&mut aggregator, 2.0).is_empty());
+    }
+
+    #[test]
+    fn sets_count_the_unique_values() {
+        let mut aggregator = aggregate(&["users:bob|s", "users:alice|s", "users:bob|s"]);
+        assert_eq!(flush(&mut aggregator, 1.0), points(&[("users", 2.0)]));
+    }
+}
@@ -1,34 +1,39 @@
-use std::{io, panic};
+use std::{io, panic, thread};
 
-use color_eyre::{config::HookBuilder, eyre};
+use color_eyre::config::HookBuilder;
 use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 
+use crate::children;
+
 /// A type alias for the terminal type used in this application
 pub type Tui = Terminal<CrosstermBackend<io::Stdout>>;
 
 /// This replaces the standard color_eyre panic and error hooks that
-/// resore the terminal before printing the panic or error.
+/// restore the terminal before printing the panic or error. A panic of the
+/// main thread stops the spawned children as well, a panic of a source thread
+/// is only logged and leaves the UI running.
 pub fn install_hooks() -> color_eyre::Result<()> {
     let (panic_hook, eyre_hook) = HookBuilder::default().into_hooks();
     // convert from a color_eyre PanicHook to a standard panic hook
     let panic_hook = panic_hook.into_panic_hook();
     panic::set_hook(Box::new(move |panic_info| {
-        restore().unwrap();
+        if thread::current().name() != Some("main") {
+            log::error!("{panic_info}");
+            return;
+        }
+        // The terminal may be gone already, the children still have to be stopped
+        let _ = restore();
         panic_hook(panic_info);
+        children::shutdown();
     }));
 
-    // convert from a color_eyre EyreHook to a eyre ErrorHook
-    let eyre_hook = eyre_hook.into_eyre_hook();
-    eyre::set_hook(Box::new(
-        move |error: &(dyn std::error::Error + 'static)| {
-            restore().unwrap();
-            eyre_hook(error)
-        },
-    ))?;
+    // The eyre hook runs whenever a report is created, including the recoverable
+    // parsing errors, so the terminal is restored by the caller of `App::run` instead
+    eyre_hook.install()?;
 
     Ok(())
 }
@@ -0,0 +1,340 @@
+use std::fmt::Display;
+
+use crate::pattern::glob_match;
+
+/// A per-series transformation of the raw values, applied before the chart scale
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transform {
+    /// Per-second rate of a monotonically increasing counter
+    Rate,
+    /// Simple moving average
+    Sma(Span),
+    /// Exponentially weighted moving average with the weight of the new value
+    Ewma(f64),
+    /// Rolling median over the number of points
+    Median(usize),
+}
+
+/// The window of a rolling transform
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Span {
+    Points(usize),
+    Seconds(f64),
+}
+
+impl Transform {
+    /// Extends the output up to the length of the input, the output is aligned
+    /// with the input and NaN marks the points without a value
+    pub fn extend(&self, times: &[f64], input: &[f64], output: &mut Vec<f64>) {
+        match self {
+            Transform::Rate => rate(times, input, output),
+            Transform::Sma(span) => rolling(times, input, *span, mean, output),
+            Transform::Ewma(alpha) => ewma(input, *alpha, output),
+            Transform::Median(points) => {
+                rolling(times, input, Span::Points(*points), median, output)
+            }
+        }
+    }
+
+    /// Smoothing transforms can be shown over the line without them
+    pub fn is_smoothing(&self) -> bool {
+        !matches!(self, Transform::Rate)
+    }
+}
+
+impl Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transform::Rate => f.write_str("rate"),
+            Transform::Sma(Span::Points(points)) => write!(f, "sma {points}"),
+            Transform::Sma(Span::Seconds(secs)) => write!(f, "sma {secs}s"),
+            Transform::Ewma(alpha) => write!(f, "ewma {alpha}"),
+            Transform::Median(points) => write!(f, "median {points}"),
+        }
+    }
+}
+
+/// A transform of the series matching the name pattern
+pub type Rule = (String, Transform);
+
+/// Splits `<GLOB>:<ARG>`, the pattern itself may contain `:`
+fn split_rule(value: &str) -> Result<(String, &str), String> {
+    match value.rsplit_once(':') {
+        Some((pattern, arg)) if !pattern.is_empty() => Ok((pattern.to_string(), arg.trim())),
+        _ => Err(format!("expected '<GLOB>:<ARG>', got {value:?}")),
+    }
+}
+
+fn parse_points(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(points) if points > 0 => Ok(points),
+        _ => Err(format!(
+            "expected a positive number of points, got {value:?}"
+        )),
+    }
+}
+
+/// Parses the `<GLOB>:<N>` or `<GLOB>:<SECONDS>s` of `--sma`
+pub fn parse_sma(value: &str) -> Result<Rule, String> {
+    let (pattern, arg) = split_rule(value)?;
+    let span = match arg.strip_suffix('s') {
+        Some(secs) => match secs.parse::<f64>() {
+            Ok(secs) if secs > 0.0 && secs.is_finite() => Span::Seconds(secs),
+            _ => {
+                return Err(format!(
+                    "expected a positive number of seconds, got {arg:?}"
+                ))
+            }
+        },
+        None => Span::Points(parse_points(arg)?),
+    };
+    Ok((pattern, Transform::Sma(span)))
+}
+
+/// Parses the `<GLOB>:<ALPHA>` of `--ewma`
+pub fn parse_ewma(value: &str) -> Result<Rule, String> {
+    let (pattern, arg) = split_rule(value)?;
+    match arg.parse::<f64>() {
+        Ok(alpha) if alpha > 0.0 && alpha <= 1.0 => Ok((pattern, Transform::Ewma(alpha))),
+        _ => Err(format!("expected an alpha in (0, 1], got {arg:?}")),
+    }
+}
+
+/// Parses the `<GLOB>:<N>` of `--median`
+pub fn parse_median(value: &str) -> Result<Rule, String> {
+    let (pattern, arg) = split_rule(value)?;
+    Ok((pattern, Transform::Median(parse_points(arg)?)))
+}
+
+/// Transforms assigned to the series by name patterns
+#[derive(Debug, Clone, Default)]
+pub struct Transforms {
+    rules: Vec<Rule>,
+}
+
+impl Transforms {
+    pub fn add(&mut self, pattern: String, transform: Transform) {
+        self.rules.push((pattern, transform));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn extend(&mut self, rules: impl IntoIterator<Item = Rule>) {
+        self.rules.extend(rules);
+    }
+
+    /// Returns the transforms of the series in the order they were added
+    pub fn pipeline(&self, name: &str) -> Vec<Transform> {
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, name))
+            .map(|(_, transform)| *transform)
+            .collect()
+    }
+}
+
+/// Outputs of every transform of a series, kept so that only the points from
+/// the first changed one on are run through the transforms again
+#[derive(Debug, Default)]
+pub struct PipelineState {
+    stages: Vec<Vec<f64>>,
+}
+
+impl PipelineState {
+    /// Runs the values from the index on through the transforms one after
+    /// another and returns the output of the last one
+    pub fn update<'a>(
+        &'a mut self,
+        pipeline: &[Transform],
+        times: &[f64],
+        input: &'a [f64],
+        from: usize,
+    ) -> &'a [f64] {
+        self.stages.resize_with(pipeline.len(), Vec::new);
+        for (idx, transform) in pipeline.iter().enumerate() {
+            let (done, rest) = self.stages.split_at_mut(idx);
+            let output = &mut rest[0];
+            output.truncate(from);
+            transform.extend(times, done.last().map_or(input, Vec::as_slice), output);
+        }
+        self.stages.last().map_or(input, Vec::as_slice)
+    }
+
+    /// Drops the outputs of the points removed from the front of the series
+    pub fn drain(&mut self, count: usize) {
+        for stage in &mut self.stages {
+            stage.drain(..count.min(stage.len()));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+}
+
+fn rate(times: &[f64], input: &[f64], output: &mut Vec<f64>) {
+    for idx in output.len()..input.len() {
+        let Some(prev) = idx.checked_sub(1) else {
+            output.push(f64::NAN);
+            continue;
+        };
+        let elapsed = times[idx] - times[prev];
+        let mut delta = input[idx] - input[prev];
+        // A counter that went down was reset, it counts from zero again
+        if delta < 0.0 {
+            delta = input[idx];
+        }
+        output.push(if elapsed > 0.0 {
+            delta / elapsed
+        } else {
+            f64::NAN
+        });
+    }
+}
+
+/// Applies the function to the finite values of the window ending at every
+/// point, the gaps stay gaps
+fn rolling(
+    times: &[f64],
+    input: &[f64],
+    span: Span,
+    reduce: fn(&mut [f64]) -> f64,
+    output: &mut Vec<f64>,
+) {
+    let mut window = Vec::new();
+    let mut start = match (span, times.get(output.len())) {
+        (Span::Seconds(secs), Some(time)) => times.partition_point(|t| *t <= time - secs),
+        _ => 0,
+    };
+    for idx in output.len()..input.len() {
+        if !input[idx].is_finite() {
+            output.push(f64::NAN);
+            continue;
+        }
+        match span {
+            Span::Points(points) => start = (idx + 1).saturating_sub(points),
+            Span::Seconds(secs) => {
+                while times[start] <= times[idx] - secs {
+                    start += 1;
+                }
+            }
+        }
+        window.clear();
+        window.extend(input[start..=idx].iter().filter(|v| v.is_finite()));
+        output.push(reduce(&mut window));
+    }
+}
+
+fn mean(values: &mut [f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+fn ewma(input: &[f64], alpha: f64, output: &mut Vec<f64>) {
+    // The output is finite exactly at the points with a value, the last one
+    // is the running average
+    let mut average = output.iter().rev().find(|v| v.is_finite()).copied();
+    for &value in &input[output.len()..] {
+        if !value.is_finite() {
+            output.push(f64::NAN);
+            continue;
+        }
+        let next = match average {
+            Some(average) => alpha * value + (1.0 - alpha) * average,
+            None => value,
+        };
+        average = Some(next);
+        output.push(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: [Transform; 4] = [
+        Transform::Rate,
+        Transform::Median(3),
+        Transform::Sma(Span::Seconds(2.5)),
+        Transform::Ewma(0.3),
+    ];
+
+    fn same(left: &[f64], right: &[f64]) -> bool {
+        left.len() == right.len()
+            && left
+                .iter()
+                .zip(right)
+                .all(|(l, r)| l == r || (l.is_nan() && r.is_nan()))
+    }
+
+    fn full(times: &[f64], input: &[f64]) -> Vec<f64> {
+        PipelineState::default()
+            .update(&PIPELINE, times, input, 0)
+            .to_vec()
+    }
+
+    #[test]
+    fn appending_matches_full_run() {
+        let mut times = Vec::new();
+        let mut input = Vec::new();
+        let mut state = PipelineState::default();
+        for idx in 0..50 {
+            times.push(idx as f64 * 0.7);
+            input.push(if idx % 11 == 5 {
+                f64::NAN
+            } else {
+                (idx * idx % 17) as f64
+            });
+            let output = state.update(&PIPELINE, &times, &input, idx).to_vec();
+            assert!(same(&output, &full(&times, &input)), "at {idx}");
+        }
+    }
+
+    #[test]
+    fn reinserted_point_matches_full_run() {
+        let mut times: Vec<f64> = (0..20).map(|idx| idx as f64).collect();
+        let mut input: Vec<f64> = (0..20).map(|idx| (idx * 3 % 7) as f64).collect();
+        let mut state = PipelineState::default();
+        state.update(&PIPELINE, &times, &input, 0);
+
+        times.insert(8, 7.5);
+        input.insert(8, 100.0);
+        let output = state.update(&PIPELINE, &times, &input, 8).to_vec();
+        assert!(same(&output, &full(&times, &input)));
+    }
+
+    #[test]
+    fn ewma_keeps_its_average_after_drain() {
+        let pipeline = [Transform::Ewma(0.5)];
+        let mut state = PipelineState::default();
+        state.update(&pipeline, &[0.0, 1.0, 2.0], &[0.0, 4.0, 8.0], 0);
+
+        state.drain(2);
+        let output = state.update(&pipeline, &[2.0, 3.0], &[8.0, 8.0], 1);
+        assert_eq!(output, [5.0, 6.5]);
+    }
+
+    #[test]
+    fn rate_handles_counter_resets() {
+        let output = PipelineState::default()
+            .update(
+                &[Transform::Rate],
+                &[0.0, 2.0, 4.0, 4.0],
+                &[10.0, 20.0, 4.0, 6.0],
+                0,
+            )
+            .to_vec();
+        assert!(same(&output, &[f64::NAN, 5.0, 2.0, f64::NAN]));
+    }
+}
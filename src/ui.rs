@@ -3,18 +3,21 @@ use std::ops::Div;
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
+    style::{Color, Modifier, Style, Stylize},
     symbols,
     text::Line,
     widgets::{
-        Axis, Block, Borders, Chart, Clear, Dataset, GraphType, LegendPosition, Row, Table, Widget,
+        Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, LegendPosition, Paragraph,
+        Row, Table, TableState, Widget,
     },
     Frame,
 };
 
 use crate::app::{self, ChartScale};
+use crate::health::{SourceHealth, SourceState};
 
 const PALETTE_DARK_CURSOR_COLOR: Color = Color::White;
+const RESTART_MARK_COLOR: Color = Color::Red;
 const PALETTE_DARK: &[Color] = &[
     Color::Indexed(3),
     Color::Indexed(27),
@@ -31,80 +34,137 @@ const PALETTE_DARK: &[Color] = &[
     Color::White,
 ];
 
+/// Color of the series from the configured colors or the default palette
+fn series_color(app: &app::App, color_idx: usize) -> Color {
+    let palette = if app.colors.is_empty() {
+        PALETTE_DARK
+    } else {
+        app.colors.as_slice()
+    };
+    palette[color_idx % palette.len()]
+}
+
 impl Widget for &app::App {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let bounds = self.chart_bounds();
-        let datasets: Vec<Dataset> = self
-            .datasets(bounds)
-            .into_iter()
-            .map(|line| {
+        let count = self.panel_count();
+        let (rows, columns) = self.layout.grid(count);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Fill(1); rows])
+            .split(area);
+        // Panels fill the rows from left to right, the last row may have fewer of them
+        for (row_idx, row) in rows.iter().enumerate() {
+            let in_row = columns.min(count - row_idx * columns);
+            let cells = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Fill(1); in_row])
+                .split(*row);
+            for (column_idx, cell) in cells.iter().enumerate() {
+                render_panel(self, row_idx * columns + column_idx, *cell, buf);
+            }
+        }
+    }
+}
+
+/// Renders a chart panel, all of them share the time axis and the cursor
+fn render_panel(app: &app::App, panel: usize, area: Rect, buf: &mut Buffer) {
+    let bounds = app.chart_bounds(panel);
+    let datasets: Vec<Dataset> = app
+        .datasets(panel)
+        .into_iter()
+        .flat_map(|line| {
+            let style = if line.restart {
+                Style::default().fg(RESTART_MARK_COLOR)
+            } else if line.dimmed {
+                Style::default()
+                    .fg(series_color(app, line.color_idx))
+                    .add_modifier(Modifier::DIM)
+            } else if line.name.is_empty() {
+                // Cursor
+                Style::default().fg(PALETTE_DARK_CURSOR_COLOR)
+            } else {
+                Style::default().fg(series_color(app, line.color_idx))
+            };
+            let mut name = Some(line.name).filter(|name| !name.is_empty());
+            line.segments.into_iter().map(move |segment| {
                 let mut ds = Dataset::default()
                     .marker(symbols::Marker::Braille)
                     .graph_type(GraphType::Line)
-                    .data(line.data);
-
-                if line.name.is_empty() {
-                    // Cursor
-                    ds = ds.style(Style::default().fg(PALETTE_DARK_CURSOR_COLOR));
-                } else {
-                    ds = ds.name(line.name).style(
-                        Style::default().fg(PALETTE_DARK[line.color_idx % PALETTE_DARK.len()]),
-                    )
+                    .style(style)
+                    .data(segment);
+                // Only the first segment of a line goes to the legend
+                if let Some(name) = name.take() {
+                    ds = ds.name(name);
                 }
                 ds
             })
-            .collect();
-
-        let last = self.elapsed();
-        let window_width = [last - self.window.as_secs_f64(), last];
-        let mut x_axis = Axis::default()
-            .style(Style::default().fg(Color::Gray))
-            .bounds(window_width);
-        let window_height = [bounds.scaled_min, bounds.scaled_max];
-        let mut y_axis = Axis::default()
-            .style(Style::default().fg(Color::Gray))
-            // .labels(vec!["-20".bold(), "0".into(), "20".bold()])
-            .bounds(window_height);
-
-        let mut legend_position = None;
-        if self.legend {
-            legend_position = Some(LegendPosition::TopLeft);
-            let mut cursor_legend = "".to_string();
-            if self.show_cursor {
-                cursor_legend = format!(" c={:.2}s", self.cursor_point());
-            }
-            y_axis = y_axis.title(format!(
-                "w={:.2?} h={:.2?} m={}s s={}{}",
-                self.window, self.history, self.move_speed, self.scale_mode, cursor_legend,
-            ));
+        })
+        .collect();
+
+    let last = app.elapsed();
+    let window_width = [last - app.window.as_secs_f64(), last];
+    let mut x_axis = Axis::default()
+        .style(Style::default().fg(Color::Gray))
+        .bounds(window_width);
+    let window_height = [bounds.scaled_min, bounds.scaled_max];
+    let mut y_axis = Axis::default()
+        .style(Style::default().fg(Color::Gray))
+        // .labels(vec!["-20".bold(), "0".into(), "20".bold()])
+        .bounds(window_height);
+
+    let mut legend_position = None;
+    if app.legend {
+        legend_position = Some(LegendPosition::TopLeft);
+        let mut cursor_legend = "".to_string();
+        if app.show_cursor {
+            cursor_legend = format!(" c={:.2}s", app.cursor_point());
         }
-        if self.axis_labels {
-            x_axis = x_axis.labels(vec![
-                format!("{:.1}s", self.elapsed() - self.window()),
-                format!("{:.1}s", self.elapsed() - self.window() / 2.0),
-                format!("{:.1}s", self.elapsed()),
-            ]);
-
-            let middle_label = if self.scale_mode == ChartScale::Liner {
-                format!("{:.2}", window_height.iter().sum::<f64>().div(2.0))
-            } else {
-                "...".to_string()
-            };
-            y_axis = y_axis.labels(vec![
-                format!("{:.2}", bounds.original_min),
-                middle_label,
-                format!("{:.2}", bounds.original_max),
-            ]);
+        if let Some(speed) = app.replay_speed() {
+            cursor_legend.push_str(&format!(" r={speed}x"));
         }
+        if let Some(search) = app.search() {
+            cursor_legend.push_str(&format!(" {search}"));
+        }
+        if let Some(status) = app.status() {
+            cursor_legend.push_str(&format!(" | {status}"));
+        }
+        // The first panel carries the state, the others are named by their series patterns
+        let title = if panel == 0 {
+            format!(
+                "w={:.2?} h={:.2?} m={}s s={}{}",
+                app.window, app.history, app.move_speed, app.scale_mode, cursor_legend,
+            )
+        } else {
+            app.panel_title(panel).to_string()
+        };
+        y_axis = y_axis.title(title);
+    }
+    if app.axis_labels {
+        x_axis = x_axis.labels(vec![
+            format!("{:.1}s", app.elapsed() - app.window()),
+            format!("{:.1}s", app.elapsed() - app.window() / 2.0),
+            format!("{:.1}s", app.elapsed()),
+        ]);
 
-        let chart = Chart::new(datasets)
-            .legend_position(legend_position)
-            .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
-            .x_axis(x_axis)
-            .y_axis(y_axis);
-
-        chart.render(area, buf);
+        let middle_label = if app.scale_mode == ChartScale::Linear {
+            format!("{:.2}", window_height.iter().sum::<f64>().div(2.0))
+        } else {
+            "...".to_string()
+        };
+        y_axis = y_axis.labels(vec![
+            format!("{:.2}", bounds.original_min),
+            middle_label,
+            format!("{:.2}", bounds.original_max),
+        ]);
     }
+
+    let chart = Chart::new(datasets)
+        .legend_position(legend_position)
+        .hidden_legend_constraints((Constraint::Min(0), Constraint::Min(0)))
+        .x_axis(x_axis)
+        .y_axis(y_axis);
+
+    chart.render(area, buf);
 }
 
 pub fn render_help(f: &mut Frame) {
@@ -119,23 +179,50 @@ pub fn render_help(f: &mut Frame) {
         Row::new(vec!["q", "quit"]),
         Row::new(vec!["Ctrl+C", "quit"]),
         Row::new(vec!["?", "show/hide this help"]),
+        Row::new(vec!["v", "show the series list"]),
+        Row::new(vec!["/", "show the series matching a regex, empty for all"]),
+        Row::new(vec!["S", "show/hide the source health"]),
         Row::new(vec!["w", "norrow the chart data window by 20%"]),
         Row::new(vec!["W", "expand the chart data window by 20%"]),
         Row::new(vec!["h", "keep 2x less history"]),
         Row::new(vec!["H", "keep 2x more history"]),
         Row::new(vec!["a", "show/hide the axis labels"]),
         Row::new(vec!["l", "show/hide the legend"]),
-        Row::new(vec!["s", "rotate the scale mode: liner, asinh"]),
+        Row::new(vec!["g", "group the legend by source"]),
+        Row::new(vec!["s", "rotate the scale mode: linear, asinh"]),
         Row::new(vec!["m", "set the window movement speed 10x slower"]),
         Row::new(vec!["M", "set the window movement speed 10x faster"]),
         Row::new(vec!["c", "show/hide the cursor"]),
+        Row::new(vec![
+            "r",
+            "toggle the transforms of all the series, of one in the series list",
+        ]),
+        Row::new(vec!["R", "show/hide the raw line behind the smoothed one"]),
         Row::new(vec!["Right", "move the cursor to the right"]),
         Row::new(vec!["Left", "move the cursor to the left"]),
         Row::new(vec!["Space", "pause the chart"]),
+        Row::new(vec!["e", "export the visible window to a file"]),
+        Row::new(vec!["E", "export the whole history to a file"]),
         Row::new(vec!["", ""]),
         Row::new(vec!["", "In pause mode"]),
         Row::new(vec!["Ctrl+Right", "move the window to the right"]),
         Row::new(vec!["Ctrl+Left", "move the window to the left"]),
+        Row::new(vec!["", ""]),
+        Row::new(vec!["", "In the series list"]),
+        Row::new(vec!["Up/Down", "select a series"]),
+        Row::new(vec!["Space", "show/hide the series"]),
+        Row::new(vec!["o", "show only the selected series"]),
+        Row::new(vec!["i", "invert the shown series"]),
+        Row::new(vec!["a", "show all the series"]),
+        Row::new(vec!["", ""]),
+        Row::new(vec!["", "In the source health"]),
+        Row::new(vec!["Up/Down", "select a source to show its stderr"]),
+        Row::new(vec!["", ""]),
+        Row::new(vec!["", "In replay mode"]),
+        Row::new(vec!["]", "seek forward by the window width"]),
+        Row::new(vec!["[", "seek backward by the window width"]),
+        Row::new(vec!["+", "replay 2x faster"]),
+        Row::new(vec!["-", "replay 2x slower"]),
     ];
     // Columns widths are constrained in the same way as Layout...
     let widths = Constraint::from_fills([3, 18]);
@@ -162,6 +249,147 @@ pub fn render_help(f: &mut Frame) {
     f.render_widget(table, area)
 }
 
+pub fn render_series(f: &mut Frame, app: &app::App) {
+    let series = app.series_list();
+    let visible = series
+        .iter()
+        .filter(|(_, _, _, _, visible)| *visible)
+        .count();
+    let popup_block = Block::default()
+        .title_top(Line::from(format!(" Series {visible}/{} ", series.len())).centered())
+        .title_bottom(
+            Line::from(
+                " Space show/hide | o solo | i invert | a show all | r transforms | v close ",
+            )
+            .centered(),
+        )
+        .borders(Borders::ALL)
+        .style(Style::default());
+
+    let area = centered_rect(60, 80, f.area());
+    let rows = series
+        .into_iter()
+        .map(|(color_idx, name, source, transforms, visible)| {
+            let mark = if visible { "[x]" } else { "[ ]" };
+            let color = series_color(app, color_idx);
+            let style = if visible {
+                Style::default().fg(color)
+            } else {
+                Style::default().fg(color).add_modifier(Modifier::DIM)
+            };
+            Row::new(vec![
+                mark.to_string(),
+                name.to_string(),
+                source.to_string(),
+                transforms,
+            ])
+            .style(style)
+        });
+    let widths = [
+        Constraint::Length(3),
+        Constraint::Fill(2),
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+    ];
+    let table = Table::new(rows, widths)
+        .column_spacing(1)
+        .block(popup_block)
+        .row_highlight_style(Style::new().reversed())
+        .highlight_symbol(">>");
+    let mut state = TableState::default().with_selected(Some(app.selected_series()));
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, area, &mut state)
+}
+
+/// Rows of the health panel taken by the stderr of the selected source
+const HEALTH_STDERR_ROWS: u16 = 12;
+
+pub fn render_health(f: &mut Frame, sources: &[SourceHealth], selected: usize) {
+    let area = centered_rect(90, 80, f.area());
+    let [table_area, stderr_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(HEALTH_STDERR_ROWS)]).areas(area);
+
+    let popup_block = Block::default()
+        .title_top(Line::from(" Sources ").centered())
+        .borders(Borders::ALL)
+        .style(Style::default());
+    let header = Row::new(vec![
+        "Source",
+        "Kind",
+        "State",
+        "Exit",
+        "Restarts",
+        "Last metric",
+        "Unparsed",
+    ])
+    .style(Style::new().bold());
+    let rows = sources.iter().map(|source| {
+        let state_style = match source.state {
+            SourceState::Running | SourceState::Finished => Style::default().fg(Color::Green),
+            SourceState::Starting | SourceState::Restarting => Style::default().fg(Color::Yellow),
+            SourceState::Failed => Style::default().fg(Color::Red),
+        };
+        let last_metric = source
+            .last_metric
+            .map(|at| format!("{:.1?} ago", at.elapsed()))
+            .unwrap_or_else(|| "never".to_string());
+        Row::new(vec![
+            Cell::from(source.name.clone()),
+            Cell::from(source.kind),
+            Cell::from(source.state.to_string()).style(state_style),
+            Cell::from(source.exit_status.clone().unwrap_or_default()),
+            Cell::from(source.restarts.to_string()),
+            Cell::from(last_metric),
+            Cell::from(source.unparsed.to_string()),
+        ])
+    });
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Length(6),
+        Constraint::Length(10),
+        Constraint::Length(20),
+        Constraint::Length(8),
+        Constraint::Length(12),
+        Constraint::Length(8),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .column_spacing(1)
+        .block(popup_block)
+        .row_highlight_style(Style::new().reversed())
+        .highlight_symbol(">>");
+    let mut state = TableState::default().with_selected(Some(selected));
+
+    // The stderr of the selected source, the latest lines that fit
+    let source = sources.get(selected);
+    let title = match source {
+        Some(source) => format!(" stderr of {} ", source.name),
+        None => " stderr ".to_string(),
+    };
+    let stderr_block = Block::default()
+        .title_top(Line::from(title).centered())
+        .title_bottom(Line::from(" Up/Down select | S close ").centered())
+        .borders(Borders::ALL);
+    let visible = stderr_area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = source
+        .map(|source| {
+            let skip = source.stderr.len().saturating_sub(visible);
+            source
+                .stderr
+                .iter()
+                .skip(skip)
+                .map(|line| Line::from(line.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let stderr = Paragraph::new(lines).block(stderr_block);
+
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, table_area, &mut state);
+    f.render_widget(stderr, stderr_area)
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
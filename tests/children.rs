@@ -0,0 +1,166 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A pseudo terminal, tlook needs one to start. Its output is drained until it is
+/// dropped, which hangs it up.
+struct Pty {
+    slave: OwnedFd,
+    _hangup: Sender<()>,
+}
+
+impl Pty {
+    fn open() -> Pty {
+        // The fds are opened close-on-exec, the tests spawn concurrently and
+        // a terminal doesn't hang up while another tlook holds its master
+        let flags = libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC;
+        // SAFETY: posix_openpt has no memory safety requirements
+        let master = unsafe { libc::posix_openpt(flags) };
+        assert!(master >= 0, "posix_openpt: {}", io::Error::last_os_error());
+        // SAFETY: the fd was just opened and is owned by nobody else
+        let master = unsafe { OwnedFd::from_raw_fd(master) };
+        let mut name = [0; 64];
+        // SAFETY: the fd is an open master and the pointer is to a live buffer of the given length
+        let res = unsafe {
+            libc::grantpt(master.as_raw_fd());
+            libc::unlockpt(master.as_raw_fd());
+            libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len())
+        };
+        assert_eq!(res, 0, "ptsname_r: {}", io::Error::last_os_error());
+        // SAFETY: ptsname_r wrote a nul terminated path to the buffer
+        let slave = unsafe { libc::open(name.as_ptr(), flags) };
+        assert!(slave >= 0, "open slave: {}", io::Error::last_os_error());
+        let size = libc::winsize {
+            ws_row: 40,
+            ws_col: 140,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: the fd is open and the pointer is to a live winsize
+        unsafe { libc::ioctl(slave, libc::TIOCSWINSZ, &size) };
+        // SAFETY: the fd was just opened and is owned by nobody else
+        let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+
+        // The screen updates block once the terminal buffer is full
+        let (hangup, hung_up) = mpsc::channel();
+        thread::spawn(move || {
+            let mut fds = libc::pollfd {
+                fd: master.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let mut master = File::from(master);
+            let mut buf = [0; 4096];
+            while hung_up.try_recv() == Err(TryRecvError::Empty) {
+                // SAFETY: the pointer is to a live pollfd of an open fd
+                if unsafe { libc::poll(&mut fds, 1, 50) } > 0 && master.read(&mut buf).is_err() {
+                    break;
+                }
+            }
+        });
+        Pty {
+            slave,
+            _hangup: hangup,
+        }
+    }
+}
+
+/// Starts tlook on the terminal as the leader of a session the terminal controls,
+/// like a shell would. The single `-p` child writes the pid of its sleep to the returned file.
+fn spawn_tlook(pty: &Pty, name: &str) -> (Child, PathBuf) {
+    let pid_file = std::env::temp_dir().join(format!("tlook-{}-{name}.pid", std::process::id()));
+    let _ = fs::remove_file(&pid_file);
+    let process = format!(
+        "sh -c 'echo $$ > {}.tmp && mv {0}.tmp {0} && exec sleep 4242'",
+        pid_file.display()
+    );
+    let mut command = Command::new(env!("CARGO_BIN_EXE_tlook"));
+    command
+        .args(["-p", &process])
+        .stdin(Stdio::from(pty.slave.try_clone().unwrap()))
+        .stdout(Stdio::from(pty.slave.try_clone().unwrap()))
+        .stderr(Stdio::null());
+    // SAFETY: setsid and ioctl are async-signal-safe
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    (command.spawn().unwrap(), pid_file)
+}
+
+fn wait_for<T>(what: &str, mut f: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn group_exists(group: libc::pid_t) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(-group, 0) == 0 }
+}
+
+/// Kills tlook with the signal, or hangs up its terminal without one,
+/// and checks that the process group of its child is gone
+fn check_group_stopped(name: &str, signal: Option<libc::c_int>) {
+    let pty = Pty::open();
+    let (mut tlook, pid_file) = spawn_tlook(&pty, name);
+    let pid: libc::pid_t = wait_for("the child to start", || {
+        fs::read_to_string(&pid_file).ok()?.trim().parse().ok()
+    });
+    let _ = fs::remove_file(&pid_file);
+    // SAFETY: getpgid has no memory safety requirements
+    let group = unsafe { libc::getpgid(pid) };
+    assert!(group > 0 && group != tlook.id() as libc::pid_t);
+
+    match signal {
+        // SAFETY: kill has no memory safety requirements
+        Some(signal) => unsafe {
+            libc::kill(tlook.id() as libc::pid_t, signal);
+        },
+        None => drop(pty),
+    }
+    let status = wait_for("tlook to exit", || tlook.try_wait().unwrap());
+    if let Some(signal) = signal {
+        assert_eq!(status.code(), Some(128 + signal));
+    }
+    // The leader may be a zombie until init reaps it
+    wait_for("the group to be gone", || {
+        (!group_exists(group)).then_some(())
+    });
+}
+
+#[test]
+fn sigterm_stops_children() {
+    check_group_stopped("sigterm", Some(libc::SIGTERM));
+}
+
+#[test]
+fn sigint_stops_children() {
+    check_group_stopped("sigint", Some(libc::SIGINT));
+}
+
+#[test]
+fn hangup_stops_children() {
+    check_group_stopped("hangup", None);
+}